@@ -0,0 +1,444 @@
+//! Admin commands for the server.
+//!
+//! Commands are typed in the terminal of the server, or sent by clients knowing the RCON password
+//! with [`RemoteCommand`] (`/rcon <password> <command>` in the chat).
+//! Type `help` for the list of commands.
+
+use core::{net::SocketAddr, str::FromStr};
+use std::sync::{Mutex, mpsc};
+#[cfg(not(target_family = "wasm"))]
+use std::{
+    io::{self, BufRead},
+    thread,
+};
+
+use bevy::{ecs::system::SystemState, prelude::*, reflect::GetPath};
+use lightyear::prelude::{server::ClientOf, *};
+
+use crate::{
+    admission::{BanList, ConnectionAttempts, Refused, refuse_client},
+    cvars::{Cvars, REGISTRY},
+    level::{CurrentLevel, level_exists, level_names},
+    protocol::{
+        CommandOutput, ConnectionRefused, ControlChannel, Player, PlayerStats, RemoteCommand,
+    },
+    server::spawn_ball,
+};
+
+/// Wrong RCON passwords after which the clients of an IP address are kicked.
+const MAX_RCON_FAILURES: u32 = 3;
+
+const HELP: &str = "\
+list                          list connected clients
+kick <client> [reason]        disconnect a client, by id or player name
+ban <client>                  kick a client and ban its IP address
+changelevel <level>           load another level
+cvars                         list cvars and their values
+set <cvar> [value]            show or change a cvar
+set <Resource.field> <value>  change a field of a resource
+spawn ball                    spawn a ball in the middle of the level";
+
+pub struct AdminPlugin;
+
+impl Plugin for AdminPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<RconPassword>()
+            .add_event::<AdminCommand>()
+            .add_systems(
+                Update,
+                (receive_remote_commands, execute_admin_commands).chain(),
+            );
+    }
+}
+
+/// Reads admin commands from the standard input of the server, one per line.
+///
+/// Browsers have no standard input, so no command is read in WASM builds.
+pub struct StdinConsolePlugin;
+
+impl Plugin for StdinConsolePlugin {
+    fn build(&self, app: &mut App) {
+        let (sender, receiver) = mpsc::channel();
+
+        // reading stdin blocks, so it is done on its own thread
+        #[cfg(not(target_family = "wasm"))]
+        thread::spawn(move || {
+            for line in io::stdin().lock().lines() {
+                let Ok(line) = line else {
+                    break;
+                };
+                if sender.send(line).is_err() {
+                    break;
+                }
+            }
+        });
+        #[cfg(target_family = "wasm")]
+        drop(sender);
+
+        app.insert_resource(StdinConsole(Mutex::new(receiver)))
+            .add_systems(Update, read_stdin_console.before(execute_admin_commands));
+    }
+}
+
+/// Password of remote commands. They are disabled when it is `None`.
+#[derive(Resource, Default)]
+pub struct RconPassword(pub Option<String>);
+
+/// A command to execute on the server.
+#[derive(Event, Debug, Clone)]
+pub struct AdminCommand {
+    pub line: String,
+    pub source: CommandSource,
+}
+
+/// Where a command comes from, and where its output goes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CommandSource {
+    Console,
+    /// Link of the client which sent the command.
+    Remote(Entity),
+}
+
+#[derive(Resource)]
+struct StdinConsole(Mutex<mpsc::Receiver<String>>);
+
+fn read_stdin_console(console: Res<StdinConsole>, mut commands: EventWriter<AdminCommand>) {
+    let Ok(receiver) = console.0.lock() else {
+        return;
+    };
+
+    for line in receiver.try_iter() {
+        commands.write(AdminCommand {
+            line,
+            source: CommandSource::Console,
+        });
+    }
+}
+
+/// Whether `guess` is `password`, in a time that doesn't tell how much of the guess is right.
+pub(crate) fn password_matches(password: &str, guess: &str) -> bool {
+    let (password, guess) = (password.as_bytes(), guess.as_bytes());
+    password.len() == guess.len()
+        && password
+            .iter()
+            .zip(guess)
+            .fold(0, |difference, (a, b)| difference | (a ^ b))
+            == 0
+}
+
+/// Execute the commands of clients sending the RCON password, and kick clients after
+/// [`MAX_RCON_FAILURES`] wrong passwords from their IP address. Failures are counted per
+/// address, so reconnecting doesn't give more guesses.
+fn receive_remote_commands(
+    mut commands: Commands,
+    password: Res<RconPassword>,
+    mut attempts: ResMut<ConnectionAttempts>,
+    mut link_q: Query<
+        (
+            Entity,
+            &RemoteId,
+            &PeerAddr,
+            &mut MessageReceiver<RemoteCommand>,
+            &mut MessageSender<CommandOutput>,
+            &mut MessageSender<ConnectionRefused>,
+        ),
+        (With<ClientOf>, Without<Refused>),
+    >,
+    mut admin_commands: EventWriter<AdminCommand>,
+) {
+    for (link, remote_id, PeerAddr(addr), mut receiver, mut sender, mut refusal_sender) in
+        link_q.iter_mut()
+    {
+        for remote_command in receiver.receive() {
+            let failures = attempts.rcon_failures.entry(addr.ip()).or_default();
+            let refusal = match &password.0 {
+                None => "Remote commands are disabled on this server",
+                Some(password)
+                    if *failures >= MAX_RCON_FAILURES
+                        || !password_matches(password, &remote_command.password) =>
+                {
+                    *failures += 1;
+                    warn!(
+                        "Client {:?} sent a wrong RCON password ({failures}/{MAX_RCON_FAILURES})",
+                        remote_id.0
+                    );
+                    if *failures >= MAX_RCON_FAILURES {
+                        refuse_client(
+                            &mut commands,
+                            link,
+                            &mut refusal_sender,
+                            "Too many wrong RCON passwords.",
+                        );
+                        break;
+                    }
+                    "Wrong RCON password"
+                }
+                Some(_) => {
+                    info!(
+                        "Remote command from {:?}: {}",
+                        remote_id.0, remote_command.command
+                    );
+                    admin_commands.write(AdminCommand {
+                        line: remote_command.command,
+                        source: CommandSource::Remote(link),
+                    });
+                    continue;
+                }
+            };
+
+            sender.send::<ControlChannel>(CommandOutput {
+                text: refusal.to_string(),
+            });
+        }
+    }
+}
+
+fn execute_admin_commands(world: &mut World) {
+    let commands: Vec<AdminCommand> = world
+        .resource_mut::<Events<AdminCommand>>()
+        .drain()
+        .collect();
+
+    for AdminCommand { line, source } in commands {
+        let output = run_command(world, &line).unwrap_or_else(|e| format!("Error: {e}"));
+
+        match source {
+            CommandSource::Console => info!("{output}"),
+            CommandSource::Remote(link) => {
+                if let Some(mut sender) = world.get_mut::<MessageSender<CommandOutput>>(link) {
+                    sender.send::<ControlChannel>(CommandOutput { text: output });
+                }
+            }
+        }
+    }
+}
+
+/// Execute a command line and return its output.
+pub fn run_command(world: &mut World, line: &str) -> Result<String, String> {
+    let words: Vec<&str> = line.split_whitespace().collect();
+
+    match words.as_slice() {
+        [] => Ok(String::new()),
+        ["help"] => Ok(HELP.to_string()),
+        ["list"] => Ok(list_clients(world)),
+        ["kick", target, reason @ ..] => {
+            let client = find_client(world, target)?;
+            let reason = match reason {
+                [] => String::from("Kicked by an admin."),
+                reason => format!("Kicked by an admin: {}", reason.join(" ")),
+            };
+            kick(world, client.link, reason);
+            Ok(format!("Kicked {}", client.describe()))
+        }
+        ["ban", target] => {
+            let client = find_client(world, target)?;
+            let mut ban_list = world.resource_mut::<BanList>();
+            ban_list.0.insert(client.addr.ip());
+            if let Err(e) = ban_list.save() {
+                error!("Failed to save the ban list: {e}");
+            }
+            kick(world, client.link, "You are banned from this server.");
+            Ok(format!("Banned {}", client.describe()))
+        }
+        ["changelevel", level] => {
+            if !level_exists(level) {
+                return Err(format!(
+                    "unknown level {level:?}, levels are {}",
+                    level_names().join(", ")
+                ));
+            }
+            *world.resource_mut::<CurrentLevel>() = CurrentLevel::load(level)?;
+            Ok(format!("Changed level to {level}"))
+        }
+        ["cvars"] => Ok(list_cvars(world.resource::<Cvars>())),
+        ["set", name] => {
+            let value = world
+                .resource::<Cvars>()
+                .get_by_name(name)
+                .ok_or_else(|| format!("unknown cvar {name:?}, see `cvars`"))?;
+            Ok(format!("{name} = {value}"))
+        }
+        ["set", path, value] if path.contains('.') => {
+            set_resource_field(world, path, value)?;
+            Ok(format!("{path} = {value}"))
+        }
+        ["set", name, value] => {
+            world.resource_mut::<Cvars>().set_str(name, value)?;
+            Ok(format!("{name} = {value}"))
+        }
+        ["spawn", "ball"] => {
+            let ball = spawn_ball(&mut world.commands(), Vec2::ZERO);
+            world.flush();
+            Ok(format!("Spawned ball {ball}"))
+        }
+        _ => Err(format!(
+            "unknown command {line:?}, type `help` for the list of commands"
+        )),
+    }
+}
+
+fn list_cvars(cvars: &Cvars) -> String {
+    REGISTRY
+        .iter()
+        .map(|info| {
+            let value = cvars.get_by_name(info.name).unwrap_or(info.default);
+            let replicated = if info.replicated { " (replicated)" } else { "" };
+            format!(
+                "{} = {value}  default {}{replicated}\n    {}",
+                info.name, info.default, info.description
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// A connected client, as seen by admin commands.
+struct ClientInfo {
+    link: Entity,
+    id: PeerId,
+    addr: SocketAddr,
+    name: Option<String>,
+    ping: Option<u32>,
+}
+
+impl ClientInfo {
+    fn describe(&self) -> String {
+        match &self.name {
+            Some(name) => format!("{name} ({:?}, {})", self.id, self.addr),
+            None => format!("{:?} ({})", self.id, self.addr),
+        }
+    }
+}
+
+fn clients(world: &mut World) -> Vec<ClientInfo> {
+    let players: Vec<(Entity, String, u32)> = world
+        .query_filtered::<(&ControlledBy, &Name, &PlayerStats), (With<Player>, With<Replicate>)>()
+        .iter(world)
+        .map(|(controlled_by, name, stats)| (controlled_by.owner, name.to_string(), stats.ping))
+        .collect();
+
+    let mut clients: Vec<ClientInfo> = world
+        .query_filtered::<(Entity, &RemoteId, &PeerAddr), (With<ClientOf>, With<Connected>)>()
+        .iter(world)
+        .map(|(link, remote_id, peer_addr)| {
+            let player = players.iter().find(|(owner, ..)| *owner == link);
+            ClientInfo {
+                link,
+                id: remote_id.0,
+                addr: peer_addr.0,
+                name: player.map(|(_, name, _)| name.clone()),
+                ping: player.map(|(.., ping)| *ping),
+            }
+        })
+        .collect();
+
+    clients.sort_by_key(|client| client.id.to_bits());
+    clients
+}
+
+fn list_clients(world: &mut World) -> String {
+    let clients = clients(world);
+    if clients.is_empty() {
+        return String::from("No clients connected");
+    }
+
+    clients
+        .iter()
+        .map(|client| {
+            format!(
+                "{:>4}  {:<16}  {:<21}  {}",
+                client.id.to_bits(),
+                client.name.as_deref().unwrap_or("(queued)"),
+                client.addr,
+                client
+                    .ping
+                    .map(|ping| format!("{ping} ms"))
+                    .unwrap_or_default(),
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Find a client by its id or by the name of its player.
+fn find_client(world: &mut World, target: &str) -> Result<ClientInfo, String> {
+    let id = target.parse::<u64>().ok();
+
+    clients(world)
+        .into_iter()
+        .find(|client| {
+            Some(client.id.to_bits()) == id
+                || client
+                    .name
+                    .as_deref()
+                    .is_some_and(|name| name.eq_ignore_ascii_case(target))
+        })
+        .ok_or_else(|| format!("no client {target:?}, see `list`"))
+}
+
+fn kick(world: &mut World, link: Entity, reason: impl Into<String>) {
+    let mut state =
+        SystemState::<(Commands, Query<&mut MessageSender<ConnectionRefused>>)>::new(world);
+    let (mut commands, mut sender_q) = state.get_mut(world);
+    if let Ok(mut sender) = sender_q.get_mut(link) {
+        refuse_client(&mut commands, link, &mut sender, reason);
+    }
+    state.apply(world);
+}
+
+/// Set a field of a reflected resource, e.g. `RollbackTolerances.position`.
+fn set_resource_field(world: &mut World, path: &str, value: &str) -> Result<(), String> {
+    let (resource_name, field_path) = path
+        .split_once('.')
+        .ok_or_else(|| format!("expected Resource.field, got {path:?}"))?;
+
+    let registry = world.resource::<AppTypeRegistry>().clone();
+    let registry = registry.read();
+
+    let reflect_resource = registry
+        .iter()
+        .filter(|registration| {
+            registration.type_info().type_path_table().short_path() == resource_name
+        })
+        .find_map(|registration| registration.data::<ReflectResource>())
+        .ok_or_else(|| format!("unknown resource {resource_name:?}"))?;
+
+    let mut resource = reflect_resource
+        .reflect_mut(world)
+        .map_err(|e| format!("{resource_name} is not available: {e:?}"))?;
+    let field = resource
+        .reflect_path_mut(field_path)
+        .map_err(|e| format!("{e}"))?;
+
+    set_field(field, value)
+}
+
+fn set_field(field: &mut dyn PartialReflect, value: &str) -> Result<(), String> {
+    fn parse<T: FromStr + 'static>(
+        field: &mut dyn PartialReflect,
+        value: &str,
+    ) -> Option<Result<(), String>> {
+        let field = field.try_downcast_mut::<T>()?;
+        Some(match value.parse() {
+            Ok(value) => {
+                *field = value;
+                Ok(())
+            }
+            Err(_) => Err(format!(
+                "{value:?} is not a valid {}",
+                core::any::type_name::<T>()
+            )),
+        })
+    }
+
+    parse::<f32>(field, value)
+        .or_else(|| parse::<f64>(field, value))
+        .or_else(|| parse::<u32>(field, value))
+        .or_else(|| parse::<u64>(field, value))
+        .or_else(|| parse::<usize>(field, value))
+        .or_else(|| parse::<i32>(field, value))
+        .or_else(|| parse::<i64>(field, value))
+        .or_else(|| parse::<bool>(field, value))
+        .or_else(|| parse::<String>(field, value))
+        .unwrap_or_else(|| Err(String::from("this field can't be set from the console")))
+}
@@ -0,0 +1,359 @@
+//! Decides which clients may join the server.
+//!
+//! Every new link is checked against the [`BanList`] and rate limited per IP address.
+//! Connected clients then introduce themselves with a [`ClientHello`]: clients with another
+//! [`ProtocolHash`] are refused, the others are [`Admitted`], or queued until a player leaves
+//! when the server is full.
+//! Refused clients are told why with a [`ConnectionRefused`] message, then disconnected
+//! once the message had time to be sent.
+
+use core::{net::IpAddr, time::Duration};
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    fs, io,
+    path::PathBuf,
+};
+
+use bevy::prelude::*;
+use lightyear::prelude::{server::ClientOf, *};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    config,
+    protocol::{ClientHello, ConnectionRefused, ControlChannel, ProtocolHash, Queued},
+};
+
+/// Time between refusing a client and disconnecting it.
+const DISCONNECT_DELAY: Duration = Duration::from_millis(500);
+
+const ADMISSION_SETTINGS_FILE: &str = "admission.ron";
+
+/// One banned IP address per line, `#` starts a comment.
+const BAN_LIST_FILE: &str = "banned_ips.txt";
+
+pub struct ServerAdmissionPlugin;
+
+impl Plugin for ServerAdmissionPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(config::load_or_default::<AdmissionSettings>(
+            ADMISSION_SETTINGS_FILE,
+        ))
+        .insert_resource(BanList::load())
+        .init_resource::<ConnectionAttempts>()
+        .add_observer(check_new_link)
+        .add_observer(refuse_pending_link)
+        .add_systems(
+            Update,
+            (
+                admit_greeted_clients,
+                admit_queued_clients,
+                disconnect_refused_clients,
+            )
+                .chain(),
+        );
+    }
+}
+
+/// What happens to clients connecting to a full server.
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum WhenFull {
+    /// Wait until a player leaves.
+    #[default]
+    Queue,
+    Reject,
+}
+
+/// Loaded from `config/admission.ron`.
+#[derive(Resource, Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct AdmissionSettings {
+    pub max_players: usize,
+    pub when_full: WhenFull,
+    /// Connection attempts allowed from one IP address during `attempt_window`.
+    pub max_attempts_per_ip: usize,
+    pub attempt_window: Duration,
+}
+
+impl Default for AdmissionSettings {
+    fn default() -> Self {
+        Self {
+            max_players: 16,
+            when_full: WhenFull::Queue,
+            max_attempts_per_ip: 5,
+            attempt_window: Duration::from_secs(60),
+        }
+    }
+}
+
+/// IP addresses which may not connect, read from `config/banned_ips.txt`.
+#[derive(Resource, Default, Debug)]
+pub struct BanList(pub HashSet<IpAddr>);
+
+impl BanList {
+    pub fn path() -> PathBuf {
+        config::config_path(BAN_LIST_FILE)
+    }
+
+    pub fn load() -> Self {
+        let path = Self::path();
+        match fs::read_to_string(&path) {
+            Ok(contents) => Self::parse(&contents),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Self::default(),
+            Err(e) => {
+                error!("Failed to read {path:?}: {e}");
+                Self::default()
+            }
+        }
+    }
+
+    pub fn parse(contents: &str) -> Self {
+        let ips = contents
+            .lines()
+            .map(|line| line.split('#').next().unwrap_or_default().trim())
+            .filter(|line| !line.is_empty())
+            .filter_map(|line| match line.parse() {
+                Ok(ip) => Some(ip),
+                Err(e) => {
+                    warn!("Ignoring invalid banned IP {line:?}: {e}");
+                    None
+                }
+            })
+            .collect();
+        Self(ips)
+    }
+
+    pub fn save(&self) -> io::Result<()> {
+        let mut ips: Vec<_> = self.0.iter().map(IpAddr::to_string).collect();
+        ips.sort();
+
+        let path = Self::path();
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(path, ips.join("\n") + "\n")
+    }
+}
+
+/// Recent connection attempts of each IP address.
+#[derive(Resource, Default)]
+pub struct ConnectionAttempts {
+    attempts: HashMap<IpAddr, VecDeque<Duration>>,
+    /// Wrong RCON passwords sent from each IP address, kept when its clients reconnect.
+    pub rcon_failures: HashMap<IpAddr, u32>,
+}
+
+impl ConnectionAttempts {
+    /// Record an attempt, returns false when `ip` made too many attempts recently.
+    fn allow(&mut self, ip: IpAddr, now: Duration, settings: &AdmissionSettings) -> bool {
+        let attempts = self.attempts.entry(ip).or_default();
+        while attempts
+            .front()
+            .is_some_and(|attempt| now.saturating_sub(*attempt) >= settings.attempt_window)
+        {
+            attempts.pop_front();
+        }
+
+        attempts.push_back(now);
+        attempts.len() <= settings.max_attempts_per_ip
+    }
+}
+
+/// Link which will be refused as soon as it is connected, because messages can't be sent before.
+#[derive(Component)]
+struct PendingRefusal(String);
+
+/// Display name requested in the [`ClientHello`] of a link. Links without it haven't
+/// introduced themselves yet, and are neither admitted nor queued.
+#[derive(Component)]
+pub struct RequestedName(pub String);
+
+/// Link of a client allowed to play. The server spawns its player when this is added.
+#[derive(Component)]
+pub struct Admitted;
+
+/// Link of a client waiting for a free slot, admitted in order of `since`.
+#[derive(Component)]
+pub struct InQueue {
+    since: Duration,
+}
+
+/// Link of a client that was refused, disconnected when the timer finishes.
+#[derive(Component)]
+pub struct Refused(Timer);
+
+/// Tell the client of `link` why it is refused and schedule its disconnection.
+pub fn refuse_client(
+    commands: &mut Commands,
+    link: Entity,
+    sender: &mut MessageSender<ConnectionRefused>,
+    reason: impl Into<String>,
+) {
+    let reason = reason.into();
+    info!("Refusing client {link:?}: {reason}");
+
+    sender.send::<ControlChannel>(ConnectionRefused { reason });
+    commands
+        .entity(link)
+        .remove::<(Admitted, InQueue)>()
+        .insert(Refused(Timer::new(DISCONNECT_DELAY, TimerMode::Once)));
+}
+
+fn check_new_link(
+    trigger: Trigger<OnAdd, LinkOf>,
+    mut commands: Commands,
+    time: Res<Time<Real>>,
+    link_q: Query<&PeerAddr>,
+    settings: Res<AdmissionSettings>,
+    ban_list: Res<BanList>,
+    mut attempts: ResMut<ConnectionAttempts>,
+) {
+    let Ok(PeerAddr(addr)) = link_q.get(trigger.target()) else {
+        return;
+    };
+    let ip = addr.ip();
+
+    let reason = if ban_list.0.contains(&ip) {
+        "You are banned from this server."
+    } else if !attempts.allow(ip, time.elapsed(), &settings) {
+        "Too many connection attempts, try again later."
+    } else {
+        return;
+    };
+
+    info!("Connection from {addr} will be refused: {reason}");
+    commands
+        .entity(trigger.target())
+        .insert(PendingRefusal(reason.to_string()));
+}
+
+fn refuse_pending_link(
+    trigger: Trigger<OnAdd, Connected>,
+    mut commands: Commands,
+    mut link_q: Query<(&PendingRefusal, &mut MessageSender<ConnectionRefused>), With<ClientOf>>,
+) {
+    let link = trigger.target();
+    if let Ok((PendingRefusal(reason), mut refusal_sender)) = link_q.get_mut(link) {
+        let reason = reason.clone();
+        refuse_client(&mut commands, link, &mut refusal_sender, reason);
+    }
+}
+
+/// Refuse the clients whose [`ClientHello`] shows another protocol, and admit or queue the
+/// others.
+fn admit_greeted_clients(
+    mut commands: Commands,
+    time: Res<Time<Real>>,
+    settings: Res<AdmissionSettings>,
+    protocol_hash: Res<ProtocolHash>,
+    mut link_q: Query<
+        (
+            Entity,
+            &mut MessageReceiver<ClientHello>,
+            &mut MessageSender<ConnectionRefused>,
+            &mut MessageSender<Queued>,
+        ),
+        (
+            With<ClientOf>,
+            With<Connected>,
+            Without<RequestedName>,
+            Without<PendingRefusal>,
+            Without<Refused>,
+        ),
+    >,
+    admitted_q: Query<(), (With<ClientOf>, With<Admitted>, With<Connected>)>,
+    queue_q: Query<(), (With<ClientOf>, With<InQueue>)>,
+) {
+    // counted as clients are admitted, the queries only see them once the commands are applied
+    let mut admitted = admitted_q.iter().count();
+    let mut queued = queue_q.iter().count();
+
+    for (link, mut receiver, mut refusal_sender, mut queue_sender) in link_q.iter_mut() {
+        let Some(hello) = receiver.receive().next() else {
+            continue;
+        };
+
+        if hello.protocol_hash != protocol_hash.0 {
+            refuse_client(
+                &mut commands,
+                link,
+                &mut refusal_sender,
+                format!(
+                    "Incompatible game version (protocol {:016x}, server has {:016x}). \
+                     Please update your game.",
+                    hello.protocol_hash, protocol_hash.0
+                ),
+            );
+            continue;
+        }
+
+        commands.entity(link).insert(RequestedName(hello.name));
+
+        if admitted < settings.max_players {
+            admitted += 1;
+            commands.entity(link).insert(Admitted);
+            continue;
+        }
+
+        match settings.when_full {
+            WhenFull::Reject => refuse_client(
+                &mut commands,
+                link,
+                &mut refusal_sender,
+                format!("The server is full ({} players).", settings.max_players),
+            ),
+            WhenFull::Queue => {
+                queued += 1;
+                let position = queued as u32;
+                info!("Server full, client {link:?} queued at position {position}");
+                queue_sender.send::<ControlChannel>(Queued { position });
+                commands.entity(link).insert(InQueue {
+                    since: time.elapsed(),
+                });
+            }
+        }
+    }
+}
+
+/// Admit queued clients when players leave, and keep the others informed of their position.
+fn admit_queued_clients(
+    mut commands: Commands,
+    settings: Res<AdmissionSettings>,
+    admitted_q: Query<(), (With<ClientOf>, With<Admitted>, With<Connected>)>,
+    mut queue_q: Query<(Entity, &InQueue, &mut MessageSender<Queued>), With<Connected>>,
+    mut left_queue: RemovedComponents<InQueue>,
+) {
+    // positions only change when a slot is freed or a client leaves the queue
+    let queue_shrunk = left_queue.read().count() > 0;
+    let free_slots = settings
+        .max_players
+        .saturating_sub(admitted_q.iter().count());
+    if (free_slots == 0 && !queue_shrunk) || queue_q.is_empty() {
+        return;
+    }
+
+    let mut queue: Vec<_> = queue_q.iter_mut().collect();
+    queue.sort_by_key(|(_, in_queue, _)| in_queue.since);
+
+    for (i, (link, _, mut sender)) in queue.into_iter().enumerate() {
+        if i < free_slots {
+            info!("Admitting queued client {link:?}");
+            commands.entity(link).remove::<InQueue>().insert(Admitted);
+        } else {
+            sender.send::<ControlChannel>(Queued {
+                position: (i - free_slots + 1) as u32,
+            });
+        }
+    }
+}
+
+fn disconnect_refused_clients(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut refused_q: Query<(Entity, &mut Refused)>,
+) {
+    for (link, mut refused) in refused_q.iter_mut() {
+        if refused.0.tick(time.delta()).just_finished() {
+            commands.trigger_targets(Disconnect, link);
+        }
+    }
+}
@@ -0,0 +1,401 @@
+//! In-game text chat.
+//!
+//! Clients send a [`ChatMessage`] to the server, which validates it and rebroadcasts
+//! it to everyone or only to the author's team as a [`ChatBroadcast`].
+
+use core::time::Duration;
+use std::collections::VecDeque;
+
+use bevy::{
+    input::{
+        ButtonState,
+        keyboard::{Key, KeyboardInput},
+    },
+    prelude::*,
+};
+use lightyear::prelude::{
+    server::{ClientOf, Server, ServerMultiMessageSender},
+    *,
+};
+
+use crate::protocol::{
+    ChatBroadcast, ChatChannel, ChatMessage, ChatScope, CommandOutput, ControlChannel, Player,
+    PlayerId, RemoteCommand, Team,
+};
+
+/// How many chat lines are kept on screen.
+const CHAT_HISTORY_LEN: usize = 8;
+
+/// Prefix that sends the message only to the author's team.
+const TEAM_CHAT_PREFIX: &str = "/t ";
+
+/// Prefix of admin commands, followed by the password and the command.
+const RCON_PREFIX: &str = "/rcon ";
+
+pub struct ServerChatPlugin;
+
+impl Plugin for ServerChatPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<ChatSettings>()
+            .init_resource::<ChatFilter>()
+            .add_observer(add_rate_limiter)
+            .add_systems(Update, receive_chat_messages);
+    }
+}
+
+pub struct ClientChatPlugin;
+
+impl Plugin for ClientChatPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<ChatInput>()
+            .add_systems(Startup, spawn_chat_box)
+            .add_systems(
+                Update,
+                (
+                    type_chat_input,
+                    toggle_chat_input,
+                    receive_chat_broadcasts,
+                    update_chat_input_text,
+                )
+                    .chain(),
+            );
+    }
+}
+
+/// Server side limits applied to every chat message.
+#[derive(Resource)]
+pub struct ChatSettings {
+    /// Maximum number of characters in a message.
+    pub max_length: usize,
+    /// Maximum number of messages a client may send within `rate_window`.
+    pub rate_limit: usize,
+    pub rate_window: Duration,
+}
+
+impl Default for ChatSettings {
+    fn default() -> Self {
+        Self {
+            max_length: 200,
+            rate_limit: 5,
+            rate_window: Duration::from_secs(5),
+        }
+    }
+}
+
+/// Hook to filter chat messages (e.g. profanity filter).
+///
+/// Returns the text that should be broadcast, or `None` to drop the message.
+/// By default messages are passed through unchanged.
+#[derive(Resource)]
+pub struct ChatFilter(pub Box<dyn Fn(&str) -> Option<String> + Send + Sync>);
+
+impl Default for ChatFilter {
+    fn default() -> Self {
+        Self(Box::new(|text| Some(text.to_string())))
+    }
+}
+
+/// Timestamps of the last messages sent by a client.
+#[derive(Component, Default)]
+struct ChatRateLimiter {
+    sent: VecDeque<Duration>,
+}
+
+impl ChatRateLimiter {
+    /// Records a message sent at `now`, returns `false` if the client is over the limit.
+    fn try_send(&mut self, now: Duration, settings: &ChatSettings) -> bool {
+        while self
+            .sent
+            .front()
+            .is_some_and(|sent| now.saturating_sub(*sent) > settings.rate_window)
+        {
+            self.sent.pop_front();
+        }
+
+        if self.sent.len() >= settings.rate_limit {
+            return false;
+        }
+
+        self.sent.push_back(now);
+        true
+    }
+}
+
+fn add_rate_limiter(trigger: Trigger<OnAdd, ClientOf>, mut commands: Commands) {
+    commands
+        .entity(trigger.target())
+        .insert(ChatRateLimiter::default());
+}
+
+/// Validate messages received from clients and rebroadcast them.
+fn receive_chat_messages(
+    mut links_q: Query<
+        (
+            &RemoteId,
+            &mut MessageReceiver<ChatMessage>,
+            &mut ChatRateLimiter,
+        ),
+        With<ClientOf>,
+    >,
+    players_q: Query<(&PlayerId, &Team, &Name), (With<Player>, With<Replicate>)>,
+    settings: Res<ChatSettings>,
+    filter: Res<ChatFilter>,
+    time: Res<Time<Real>>,
+    // one server per transport
+    server_q: Query<&Server>,
+    mut sender: ServerMultiMessageSender,
+) {
+    for (remote_id, mut receiver, mut rate_limiter) in links_q.iter_mut() {
+        for message in receiver.receive() {
+            let text = message.text.trim();
+
+            if text.is_empty() || text.chars().count() > settings.max_length {
+                warn!("Dropped invalid chat message from {:?}", remote_id.0);
+                continue;
+            }
+
+            if !rate_limiter.try_send(time.elapsed(), &settings) {
+                warn!("Client {:?} is sending chat messages too fast", remote_id.0);
+                continue;
+            }
+
+            let Some(text) = (filter.0)(text) else {
+                continue;
+            };
+
+            let target = match message.scope {
+                ChatScope::All => NetworkTarget::All,
+                ChatScope::Team => {
+                    let Some((_, team, _)) =
+                        players_q.iter().find(|(id, ..)| id.0 == remote_id.0)
+                    else {
+                        continue;
+                    };
+
+                    NetworkTarget::Only(
+                        players_q
+                            .iter()
+                            .filter(|(_, other_team, _)| *other_team == team)
+                            .map(|(id, ..)| id.0)
+                            .collect(),
+                    )
+                }
+            };
+
+            let author = players_q
+                .iter()
+                .find(|(id, ..)| id.0 == remote_id.0)
+                .map_or_else(
+                    || format!("Player {}", remote_id.0.to_bits()),
+                    |(.., name)| name.to_string(),
+                );
+
+            let broadcast = ChatBroadcast {
+                author,
+                scope: message.scope,
+                text,
+            };
+
+            for server in server_q.iter() {
+                if let Err(e) = sender.send::<_, ChatChannel>(&broadcast, server, &target) {
+                    error!("Failed to broadcast chat message: {e:?}");
+                }
+            }
+        }
+    }
+}
+
+/// State of the chat input line on the client.
+#[derive(Resource, Default)]
+pub struct ChatInput {
+    pub open: bool,
+    pub buffer: String,
+}
+
+/// Run condition: is the player currently typing a chat message.
+pub fn chat_input_open(chat_input: Option<Res<ChatInput>>) -> bool {
+    chat_input.is_some_and(|chat_input| chat_input.open)
+}
+
+#[derive(Component)]
+struct ChatLog;
+
+#[derive(Component)]
+struct ChatInputText;
+
+fn spawn_chat_box(mut commands: Commands) {
+    commands
+        .spawn((
+            Name::new("Chat box"),
+            Node {
+                position_type: PositionType::Absolute,
+                left: Val::Px(8.),
+                bottom: Val::Px(8.),
+                width: Val::Px(320.),
+                flex_direction: FlexDirection::Column,
+                ..default()
+            },
+        ))
+        .with_children(|parent| {
+            parent.spawn((
+                ChatLog,
+                Node {
+                    flex_direction: FlexDirection::Column,
+                    ..default()
+                },
+            ));
+            parent.spawn((
+                ChatInputText,
+                Text::default(),
+                TextFont {
+                    font_size: 14.,
+                    ..default()
+                },
+                BackgroundColor(Color::srgba(0., 0., 0., 0.5)),
+                Visibility::Hidden,
+            ));
+        });
+}
+
+/// Open the input line with Enter, send the typed message with Enter and cancel with Escape.
+///
+/// While it is open, the gameplay input of the controlled player is disabled by the
+/// [`EditorPlugin`](crate::editor::EditorPlugin), like while typing in the editor.
+fn toggle_chat_input(
+    keyboard: Res<ButtonInput<KeyCode>>,
+    mut chat_input: ResMut<ChatInput>,
+    mut client: Single<
+        (
+            &mut MessageSender<ChatMessage>,
+            &mut MessageSender<RemoteCommand>,
+        ),
+        With<Client>,
+    >,
+) {
+    if keyboard.just_pressed(KeyCode::Enter) {
+        if chat_input.open {
+            let buffer = core::mem::take(&mut chat_input.buffer);
+            let (chat_sender, command_sender) = &mut *client;
+
+            if let Some(rcon) = buffer.strip_prefix(RCON_PREFIX) {
+                let (password, command) = rcon.split_once(' ').unwrap_or((rcon, ""));
+                command_sender.send::<ControlChannel>(RemoteCommand {
+                    password: password.to_string(),
+                    command: command.to_string(),
+                });
+            } else {
+                let message = match buffer.strip_prefix(TEAM_CHAT_PREFIX) {
+                    Some(text) => ChatMessage {
+                        scope: ChatScope::Team,
+                        text: text.to_string(),
+                    },
+                    None => ChatMessage {
+                        scope: ChatScope::All,
+                        text: buffer,
+                    },
+                };
+
+                if !message.text.trim().is_empty() {
+                    chat_sender.send::<ChatChannel>(message);
+                }
+            }
+        }
+        chat_input.open = !chat_input.open;
+    } else if chat_input.open && keyboard.just_pressed(KeyCode::Escape) {
+        chat_input.buffer.clear();
+        chat_input.open = false;
+    }
+}
+
+fn type_chat_input(mut events: EventReader<KeyboardInput>, mut chat_input: ResMut<ChatInput>) {
+    if !chat_input.open {
+        events.clear();
+        return;
+    }
+
+    for event in events.read() {
+        if event.state != ButtonState::Pressed {
+            continue;
+        }
+
+        match &event.logical_key {
+            Key::Character(text) => chat_input.buffer.push_str(text),
+            Key::Space => chat_input.buffer.push(' '),
+            Key::Backspace => {
+                chat_input.buffer.pop();
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Show chat messages and the output of admin commands in the chat log.
+fn receive_chat_broadcasts(
+    mut commands: Commands,
+    mut client: Single<
+        (
+            &mut MessageReceiver<ChatBroadcast>,
+            &mut MessageReceiver<CommandOutput>,
+        ),
+        With<Client>,
+    >,
+    chat_log: Single<(Entity, Option<&Children>), With<ChatLog>>,
+) {
+    let (chat_log, lines) = chat_log.into_inner();
+    let mut line_count = lines.map_or(0, |lines| lines.len());
+    let mut lines = lines.into_iter().flatten();
+
+    let (chat_receiver, output_receiver) = &mut *client;
+    let messages = chat_receiver.receive().map(|message| {
+        let (prefix, color) = match message.scope {
+            ChatScope::All => ("", Color::WHITE),
+            ChatScope::Team => ("[team] ", Color::srgb(0.4, 0.8, 1.0)),
+        };
+        (
+            format!("{prefix}{}: {}", message.author, message.text),
+            color,
+        )
+    });
+    let outputs = output_receiver
+        .receive()
+        .map(|output| (output.text, Color::srgb(1.0, 0.8, 0.3)));
+
+    for (text, color) in messages.chain(outputs) {
+        let line = commands
+            .spawn((
+                Text::new(text),
+                TextFont {
+                    font_size: 14.,
+                    ..default()
+                },
+                TextColor(color),
+            ))
+            .id();
+        commands.entity(chat_log).add_child(line);
+
+        line_count += 1;
+        if line_count > CHAT_HISTORY_LEN {
+            if let Some(oldest) = lines.next() {
+                commands.entity(*oldest).despawn();
+            }
+            line_count -= 1;
+        }
+    }
+}
+
+fn update_chat_input_text(
+    chat_input: Res<ChatInput>,
+    input_text: Single<(&mut Text, &mut Visibility), With<ChatInputText>>,
+) {
+    if !chat_input.is_changed() {
+        return;
+    }
+
+    let (mut text, mut visibility) = input_text.into_inner();
+    text.0 = format!("> {}", chat_input.buffer);
+    *visibility = if chat_input.open {
+        Visibility::Inherited
+    } else {
+        Visibility::Hidden
+    };
+}
@@ -4,22 +4,56 @@ use avian2d::prelude::{Collider, RigidBody};
 use bevy::prelude::*;
 use lightyear::{
     netcode::{Key, NetcodeClient},
-    prelude::{client::NetcodeConfig, *},
+    prelude::{
+        client::{NetcodeConfig, WebSocketClientIo, WebTransportClientIo},
+        *,
+    },
+    websocket::client::ClientConfig as WebSocketClientConfig,
 };
 
 use crate::{
-    protocol::{CliClientOptions, Player, PlayerAction},
-    shared::{self, SERVER_ADDR},
+    chat::ClientChatPlugin,
+    cvars::{ClientCvarsPlugin, Cvars, PLAYER_SPEED},
+    level::ClientLevelPlugin,
+    net_stats::NetStatsPlugin,
+    prediction_gizmos::PredictionGizmosPlugin,
+    protocol::{
+        ClientHello, CliClientOptions, ConnectionRefused, ControlChannel, Player, PlayerAction,
+        ProtocolHash, Queued,
+    },
+    rollback_diagnostics::RollbackDiagnosticsPlugin,
+    scoreboard::ScoreboardPlugin,
+    shared::{self, LinkConditions},
+    transport::{MemoryClientLink, Transport},
 };
 
 pub struct MyClientPlugin;
 
 impl Plugin for MyClientPlugin {
     fn build(&self, app: &mut App) {
-        app.add_systems(Update, (setup,));
+        app.add_plugins((
+            ClientChatPlugin,
+            ClientCvarsPlugin,
+            ClientLevelPlugin,
+            ScoreboardPlugin,
+            NetStatsPlugin,
+            RollbackDiagnosticsPlugin,
+            PredictionGizmosPlugin,
+        ));
+
+        app.init_resource::<Transport>();
+
+        app.add_systems(
+            Update,
+            (setup, update_nameplates, receive_connection_status),
+        );
 
         app.add_systems(FixedUpdate, (player_movement,));
 
+        app.add_observer(send_client_hello);
+
+        app.add_observer(clear_connection_status);
+
         app.add_observer(on_predicted_player_connect);
 
         app.add_observer(on_interpolated_player_spawn);
@@ -31,10 +65,13 @@ impl Plugin for MyClientPlugin {
 fn setup(
     mut commands: Commands,
     client_added_q: Query<(Entity, &CliClientOptions), Added<CliClientOptions>>,
+    link_conditions: Res<LinkConditions>,
+    transport: Res<Transport>,
+    mut memory_link: Option<ResMut<MemoryClientLink>>,
 ) {
     for (client_entity, client_id) in client_added_q.iter() {
         let auth = Authentication::Manual {
-            server_addr: SERVER_ADDR,
+            server_addr: client_id.server_addr,
             client_id: client_id.id,
             private_key: Key::default(),
             protocol_id: 0,
@@ -46,19 +83,145 @@ fn setup(
             Name::new(format!("Netcode client {}", client_id.id)),
             Client::default(),
             LocalAddr(clien_address),
-            PeerAddr(SERVER_ADDR),
-            Link::new(None),
+            PeerAddr(client_id.server_addr),
+            Link::new(link_conditions.recv_conditioner()),
             ReplicationReceiver::default(),
             PredictionManager::default(),
             InterpolationManager::default(),
             NetcodeClient::new(auth, NetcodeConfig::default()).unwrap(),
-            UdpIo::default(),
         ));
 
+        match *transport {
+            #[cfg(not(target_family = "wasm"))]
+            Transport::Udp => {
+                commands.entity(client_entity).insert(UdpIo::default());
+            }
+            #[cfg(target_family = "wasm")]
+            Transport::Udp => {
+                error!("Browsers can't use UDP, join with the WebTransport or WebSocket transport");
+                continue;
+            }
+            Transport::WebTransport => {
+                commands.entity(client_entity).insert(WebTransportClientIo {
+                    certificate_digest: client_id.certificate_digest.clone(),
+                });
+            }
+            Transport::WebSocket => {
+                commands.entity(client_entity).insert(WebSocketClientIo {
+                    config: WebSocketClientConfig::default(),
+                });
+            }
+            Transport::Memory => {
+                let Some(io) = memory_link.as_mut().and_then(|link| link.0.take()) else {
+                    error!(
+                        "No in-memory link available for client {}, the memory transport needs a server in the same process (`host` mode)",
+                        client_id.id
+                    );
+                    continue;
+                };
+                commands.entity(client_entity).insert(io);
+            }
+        }
+
         commands.trigger_targets(Connect, client_entity);
     }
 }
 
+/// Introduce ourselves to the server as soon as the connection is established.
+fn send_client_hello(
+    trigger: Trigger<OnAdd, Connected>,
+    mut client_q: Query<(&CliClientOptions, &mut MessageSender<ClientHello>), With<Client>>,
+    protocol_hash: Res<ProtocolHash>,
+) {
+    let Ok((options, mut sender)) = client_q.get_mut(trigger.target()) else {
+        return;
+    };
+
+    sender.send::<ControlChannel>(ClientHello {
+        name: options.name.clone(),
+        protocol_hash: protocol_hash.0,
+    });
+}
+
+/// Message from the server about this client's admission: its position in the queue of a full
+/// server, or the reason it was refused, displayed until the game is closed.
+#[derive(Component)]
+struct ConnectionStatusText;
+
+fn show_connection_status(
+    commands: &mut Commands,
+    text_q: &Query<Entity, With<ConnectionStatusText>>,
+    text: String,
+    color: Color,
+) {
+    for entity in text_q.iter() {
+        commands.entity(entity).despawn();
+    }
+    commands.spawn((
+        Name::new("Connection status"),
+        ConnectionStatusText,
+        Text::new(text),
+        TextColor(color),
+        TextLayout::new_with_justify(JustifyText::Center),
+        Node {
+            position_type: PositionType::Absolute,
+            top: Val::Percent(40.),
+            width: Val::Percent(100.),
+            justify_content: JustifyContent::Center,
+            ..default()
+        },
+        BackgroundColor(Color::srgba(0., 0., 0., 0.7)),
+    ));
+}
+
+fn receive_connection_status(
+    mut commands: Commands,
+    mut client: Single<
+        (
+            &mut MessageReceiver<ConnectionRefused>,
+            &mut MessageReceiver<Queued>,
+        ),
+        With<Client>,
+    >,
+    text_q: Query<Entity, With<ConnectionStatusText>>,
+) {
+    let (refused_receiver, queued_receiver) = &mut *client;
+
+    for queued in queued_receiver.receive() {
+        info!("Server full, position in queue: {}", queued.position);
+        show_connection_status(
+            &mut commands,
+            &text_q,
+            format!(
+                "The server is full.\nPosition in queue: {}",
+                queued.position
+            ),
+            Color::WHITE,
+        );
+    }
+
+    for refused in refused_receiver.receive() {
+        warn!("Connection refused by the server: {}", refused.reason);
+        show_connection_status(
+            &mut commands,
+            &text_q,
+            format!("Disconnected by the server:\n{}", refused.reason),
+            Color::srgb(1., 0.4, 0.4),
+        );
+    }
+}
+
+/// The queue status is no longer relevant once the server spawned our player.
+fn clear_connection_status(
+    _trigger: Trigger<OnAdd, Controlled>,
+    mut commands: Commands,
+    text_q: Query<Entity, With<ConnectionStatusText>>,
+) {
+    for entity in text_q.iter() {
+        commands.entity(entity).despawn();
+    }
+}
+
 /// Blueprint pattern: when the ball gets replicated from the server, add all the components
 /// that we need that are not replicated.
 /// (for example physical properties that are constant, so they don't need to be networked)
@@ -92,13 +255,15 @@ fn player_movement(
         ),
         With<Predicted>,
     >,
+    cvars: Res<Cvars>,
 ) {
+    let speed = cvars.get(&PLAYER_SPEED);
     // let tick = timeline.tick();
     for (mut velocity, input) in position_query.iter_mut() {
         // trace!(?tick, ?position, ?input, "client");
         // NOTE: be careful to directly pass Mut<PlayerPosition>
         // getting a mutable reference triggers change detection, unless you use `as_deref_mut()`
-        shared::move_player(&mut velocity, input);
+        shared::move_player(&mut velocity, input, speed);
     }
 }
 
@@ -162,3 +327,47 @@ fn on_interpolated_player_spawn(
 
     warn!("Interpolated player spawned!");
 }
+
+/// Text displayed above a player with its name.
+#[derive(Component)]
+struct Nameplate;
+
+/// Spawn a nameplate above every displayed player and keep it in sync with its `Name`.
+fn update_nameplates(
+    mut commands: Commands,
+    player_q: Query<
+        (Entity, &Name, Option<&Children>),
+        (
+            With<Player>,
+            Or<(With<Predicted>, With<Interpolated>)>,
+            Changed<Name>,
+        ),
+    >,
+    mut nameplate_q: Query<&mut Text2d, With<Nameplate>>,
+) {
+    for (entity, name, children) in player_q.iter() {
+        let existing = children
+            .into_iter()
+            .flatten()
+            .find(|child| nameplate_q.contains(**child));
+
+        match existing {
+            Some(nameplate) => {
+                if let Ok(mut text) = nameplate_q.get_mut(*nameplate) {
+                    text.0 = name.to_string();
+                }
+            }
+            None => {
+                commands.entity(entity).with_child((
+                    Nameplate,
+                    Text2d::new(name.to_string()),
+                    TextFont {
+                        font_size: 14.,
+                        ..default()
+                    },
+                    Transform::from_xyz(0., 45., 1.),
+                ));
+            }
+        }
+    }
+}
@@ -0,0 +1,52 @@
+//! Optional RON configuration files, read from the [`CONFIG_DIR`] directory
+//! next to where the game is started.
+
+use std::{fs, io, path::PathBuf};
+
+use bevy::prelude::*;
+use serde::{Serialize, de::DeserializeOwned};
+
+pub const CONFIG_DIR: &str = "config";
+
+pub fn config_path(file_name: &str) -> PathBuf {
+    PathBuf::from(CONFIG_DIR).join(file_name)
+}
+
+/// Load a configuration file, falling back to the default value when it is missing or invalid.
+pub fn load_or_default<T: DeserializeOwned + Default>(file_name: &str) -> T {
+    let path = config_path(file_name);
+
+    let contents = match fs::read_to_string(&path) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            info!("No {path:?} found, using defaults");
+            return T::default();
+        }
+        Err(e) => {
+            error!("Failed to read {path:?}: {e}");
+            return T::default();
+        }
+    };
+
+    ron::from_str(&contents).unwrap_or_else(|e| {
+        error!("Failed to parse {path:?}, using defaults: {e}");
+        T::default()
+    })
+}
+
+/// Save a configuration file, creating [`CONFIG_DIR`] if needed. Errors are logged.
+pub fn save<T: Serialize>(file_name: &str, value: &T) {
+    let path = config_path(file_name);
+
+    let contents = match ron::ser::to_string_pretty(value, ron::ser::PrettyConfig::default()) {
+        Ok(contents) => contents,
+        Err(e) => {
+            error!("Failed to serialize {path:?}: {e}");
+            return;
+        }
+    };
+
+    if let Err(e) = fs::create_dir_all(CONFIG_DIR).and_then(|()| fs::write(&path, contents)) {
+        error!("Failed to write {path:?}: {e}");
+    }
+}
@@ -0,0 +1,360 @@
+//! Console variables: typed, named settings with defaults.
+//!
+//! Every cvar is declared as a [`Cvar`] constant and listed in [`REGISTRY`].
+//! Values are read from `config/cvars.ron`, overridden with `--cvar name=value` on the command line,
+//! and can be changed at runtime with the `set` admin command or from the editor.
+//! Replicated cvars affect the simulation, so the server sends their values to clients
+//! to predict with the same values.
+
+use core::time::Duration;
+use std::collections::HashMap;
+
+use bevy::prelude::*;
+use lightyear::prelude::{
+    server::{Server, ServerMultiMessageSender},
+    *,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    admission::Admitted,
+    config,
+    protocol::{ControlChannel, CvarSync},
+};
+
+const CVARS_FILE: &str = "cvars.ron";
+
+pub const TICK_RATE: Cvar<f32> = Cvar {
+    name: "tick_rate",
+    description: "Fixed updates per second, read at startup. Must be the same on clients and server.",
+    default: 64.,
+    replicated: false,
+};
+
+pub const REPLICATION_INTERVAL_MS: Cvar<i64> = Cvar {
+    name: "replication_interval_ms",
+    description: "Time between replication updates sent by the server, for new clients.",
+    default: 100,
+    replicated: false,
+};
+
+pub const PLAYER_SPEED: Cvar<f32> = Cvar {
+    name: "player_speed",
+    description: "Speed of players, in pixels per second.",
+    default: 150.,
+    replicated: true,
+};
+
+pub const BULLET_SPEED: Cvar<f32> = Cvar {
+    name: "bullet_speed",
+    description: "Speed of bullets, in pixels per second.",
+    default: 20.,
+    replicated: true,
+};
+
+/// Every cvar, so that they can be listed and set by name.
+pub static REGISTRY: [CvarInfo; 4] = [
+    TICK_RATE.info(),
+    REPLICATION_INTERVAL_MS.info(),
+    PLAYER_SPEED.info(),
+    BULLET_SPEED.info(),
+];
+
+pub struct CvarsPlugin;
+
+impl Plugin for CvarsPlugin {
+    fn build(&self, app: &mut App) {
+        app.register_type::<Cvars>();
+        // inserted by `main` before the plugins when set from the command line
+        if !app.world().contains_resource::<Cvars>() {
+            app.insert_resource(Cvars::load(&[]));
+        }
+    }
+}
+
+pub struct ServerCvarsPlugin;
+
+impl Plugin for ServerCvarsPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_observer(send_cvars_to_new_client)
+            .add_systems(Update, broadcast_cvars.run_if(resource_changed::<Cvars>));
+    }
+}
+
+pub struct ClientCvarsPlugin;
+
+impl Plugin for ClientCvarsPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Update, receive_cvars);
+    }
+}
+
+/// Value of a cvar.
+#[derive(Serialize, Deserialize, Reflect, Debug, Clone, Copy, PartialEq)]
+pub enum CvarValue {
+    Float(f32),
+    Int(i64),
+    Bool(bool),
+}
+
+/// Value of a cvar in `config/cvars.ron`, typed by RON.
+#[derive(Deserialize, Debug)]
+#[serde(untagged)]
+pub enum ConfigValue {
+    Int(i64),
+    Float(f64),
+    Bool(bool),
+    /// Any other RON value, reported as invalid with the name of its cvar.
+    Other(ron::Value),
+}
+
+impl CvarValue {
+    /// Parse `value` as the same type as `self`.
+    fn parse_as(&self, value: &str) -> Result<Self, String> {
+        let parsed = match self {
+            CvarValue::Float(_) => value.parse().map(CvarValue::Float).ok(),
+            CvarValue::Int(_) => value.parse().map(CvarValue::Int).ok(),
+            CvarValue::Bool(_) => value.parse().map(CvarValue::Bool).ok(),
+        };
+        parsed.ok_or_else(|| format!("{value:?} is not a valid {}", self.type_name()))
+    }
+
+    /// Convert `value` to the same type as `self`. Integers are accepted for numbers.
+    fn convert_as(&self, value: ConfigValue) -> Result<Self, String> {
+        match (self, value) {
+            (CvarValue::Float(_), ConfigValue::Float(value)) => Ok(CvarValue::Float(value as f32)),
+            (CvarValue::Float(_), ConfigValue::Int(value)) => Ok(CvarValue::Float(value as f32)),
+            (CvarValue::Int(_), ConfigValue::Int(value)) => Ok(CvarValue::Int(value)),
+            (CvarValue::Bool(_), ConfigValue::Bool(value)) => Ok(CvarValue::Bool(value)),
+            (_, value) => Err(format!("{value:?} is not a valid {}", self.type_name())),
+        }
+    }
+
+    fn type_name(&self) -> &'static str {
+        match self {
+            CvarValue::Float(_) => "number",
+            CvarValue::Int(_) => "integer",
+            CvarValue::Bool(_) => "boolean",
+        }
+    }
+}
+
+impl core::fmt::Display for CvarValue {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            CvarValue::Float(value) => value.fmt(f),
+            CvarValue::Int(value) => value.fmt(f),
+            CvarValue::Bool(value) => value.fmt(f),
+        }
+    }
+}
+
+/// Rust types which cvars can have.
+pub trait CvarType: Copy {
+    fn from_value(value: CvarValue) -> Option<Self>;
+}
+
+impl CvarType for f32 {
+    fn from_value(value: CvarValue) -> Option<Self> {
+        match value {
+            CvarValue::Float(value) => Some(value),
+            _ => None,
+        }
+    }
+}
+
+impl CvarType for i64 {
+    fn from_value(value: CvarValue) -> Option<Self> {
+        match value {
+            CvarValue::Int(value) => Some(value),
+            _ => None,
+        }
+    }
+}
+
+impl CvarType for bool {
+    fn from_value(value: CvarValue) -> Option<Self> {
+        match value {
+            CvarValue::Bool(value) => Some(value),
+            _ => None,
+        }
+    }
+}
+
+/// Declaration of a cvar of type `T`.
+pub struct Cvar<T> {
+    pub name: &'static str,
+    pub description: &'static str,
+    pub default: T,
+    /// Sent by the server to clients.
+    pub replicated: bool,
+}
+
+/// Declaration of a cvar, without its type.
+pub struct CvarInfo {
+    pub name: &'static str,
+    pub description: &'static str,
+    pub default: CvarValue,
+    pub replicated: bool,
+}
+
+macro_rules! impl_cvar_info {
+    ($t:ty, $variant:ident) => {
+        impl Cvar<$t> {
+            pub const fn info(&self) -> CvarInfo {
+                CvarInfo {
+                    name: self.name,
+                    description: self.description,
+                    default: CvarValue::$variant(self.default),
+                    replicated: self.replicated,
+                }
+            }
+        }
+    };
+}
+
+impl_cvar_info!(f32, Float);
+impl_cvar_info!(i64, Int);
+impl_cvar_info!(bool, Bool);
+
+pub fn find_cvar(name: &str) -> Option<&'static CvarInfo> {
+    REGISTRY.iter().find(|info| info.name == name)
+}
+
+/// Current value of every cvar.
+#[derive(Resource, Reflect, Debug, Clone)]
+#[reflect(Resource)]
+pub struct Cvars {
+    values: HashMap<String, CvarValue>,
+}
+
+impl Default for Cvars {
+    fn default() -> Self {
+        Self {
+            values: REGISTRY
+                .iter()
+                .map(|info| (info.name.to_string(), info.default))
+                .collect(),
+        }
+    }
+}
+
+impl Cvars {
+    /// Defaults, overridden by `config/cvars.ron`, then by `overrides` from the command line.
+    pub fn load(overrides: &[(String, String)]) -> Self {
+        let mut cvars = Self::default();
+
+        let file: HashMap<String, ConfigValue> = config::load_or_default(CVARS_FILE);
+        for (name, value) in file {
+            if let Err(e) = cvars.set_config(&name, value) {
+                error!("Invalid cvar {name} in {CVARS_FILE}: {e}");
+            }
+        }
+        for (name, value) in overrides {
+            if let Err(e) = cvars.set_str(name, value) {
+                error!("Invalid cvar {name} on the command line: {e}");
+            }
+        }
+
+        cvars
+    }
+
+    pub fn get<T: CvarType>(&self, cvar: &Cvar<T>) -> T {
+        self.values
+            .get(cvar.name)
+            .copied()
+            .and_then(T::from_value)
+            .unwrap_or(cvar.default)
+    }
+
+    pub fn get_by_name(&self, name: &str) -> Option<CvarValue> {
+        let info = find_cvar(name)?;
+        Some(self.values.get(name).copied().unwrap_or(info.default))
+    }
+
+    /// Parse and set the value of the cvar named `name`.
+    pub fn set_str(&mut self, name: &str, value: &str) -> Result<(), String> {
+        let info = find_cvar(name).ok_or_else(|| format!("unknown cvar {name:?}"))?;
+        let value = info.default.parse_as(value)?;
+        self.values.insert(name.to_string(), value);
+        Ok(())
+    }
+
+    /// Set the value of the cvar named `name` read from `config/cvars.ron`.
+    pub fn set_config(&mut self, name: &str, value: ConfigValue) -> Result<(), String> {
+        let info = find_cvar(name).ok_or_else(|| format!("unknown cvar {name:?}"))?;
+        let value = info.default.convert_as(value)?;
+        self.values.insert(name.to_string(), value);
+        Ok(())
+    }
+
+    pub fn tick_duration(&self) -> Duration {
+        Duration::from_secs_f64(1.0 / f64::from(self.get(&TICK_RATE).max(1.)))
+    }
+
+    pub fn replication_interval(&self) -> Duration {
+        Duration::from_millis(self.get(&REPLICATION_INTERVAL_MS).max(0) as u64)
+    }
+
+    fn replicated(&self) -> CvarSync {
+        CvarSync {
+            values: REGISTRY
+                .iter()
+                .filter(|info| info.replicated)
+                .filter_map(|info| Some((info.name.to_string(), self.get_by_name(info.name)?)))
+                .collect(),
+        }
+    }
+}
+
+/// Parse a `name=value` command line argument.
+pub fn parse_cvar_arg(arg: &str) -> Result<(String, String), String> {
+    let (name, value) = arg
+        .split_once('=')
+        .ok_or_else(|| format!("expected name=value, got {arg:?}"))?;
+    Ok((name.trim().to_string(), value.trim().to_string()))
+}
+
+fn send_cvars_to_new_client(
+    trigger: Trigger<OnAdd, Admitted>,
+    cvars: Res<Cvars>,
+    mut sender_q: Query<&mut MessageSender<CvarSync>>,
+) {
+    if let Ok(mut sender) = sender_q.get_mut(trigger.target()) {
+        sender.send::<ControlChannel>(cvars.replicated());
+    }
+}
+
+fn broadcast_cvars(
+    cvars: Res<Cvars>,
+    // one server per transport
+    server_q: Query<&Server>,
+    mut sender: ServerMultiMessageSender,
+) {
+    // clients receive the cvars when they are admitted
+    if cvars.is_added() {
+        return;
+    }
+
+    let message = cvars.replicated();
+    for server in server_q.iter() {
+        if let Err(e) = sender.send::<_, ControlChannel>(&message, server, &NetworkTarget::All) {
+            error!("Failed to broadcast cvars: {e:?}");
+        }
+    }
+}
+
+fn receive_cvars(
+    mut receiver: Single<&mut MessageReceiver<CvarSync>, With<Client>>,
+    mut cvars: ResMut<Cvars>,
+) {
+    for sync in receiver.receive() {
+        for (name, value) in sync.values {
+            if find_cvar(&name).is_none_or(|info| !info.replicated) {
+                warn!("The server sent the unknown cvar {name:?}");
+                continue;
+            }
+            cvars.values.insert(name, value);
+        }
+    }
+}
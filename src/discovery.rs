@@ -0,0 +1,414 @@
+//! LAN server discovery.
+//!
+//! Servers periodically broadcast a small UDP [`ServerBeacon`] on the local network (and on
+//! loopback, so that a server and a client on the same machine find each other).
+//! Clients started with `--browse` listen for beacons and show a menu of the servers to join.
+//! When the discovery port can't be opened, they join the server given on the command line
+//! instead and show why.
+
+use core::{net::SocketAddr, time::Duration};
+use std::{
+    collections::HashMap,
+    io,
+    net::{Ipv4Addr, UdpSocket},
+};
+
+use bevy::prelude::*;
+use lightyear::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    level::CurrentLevel,
+    protocol::{CliClientOptions, Player, ProtocolHash},
+    transport::Transport,
+};
+
+/// Port on which clients listen for beacons.
+pub const DISCOVERY_PORT: u16 = 5050;
+
+const BEACON_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Servers which did not send a beacon for this long are removed from the list.
+const SERVER_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Largest beacon accepted, in bytes.
+const MAX_BEACON_SIZE: usize = 1024;
+
+/// Description of a server, broadcast on the local network.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ServerBeacon {
+    pub name: String,
+    pub map: String,
+    pub players: u32,
+    /// [`ProtocolHash`] of the server, clients only list servers with the same one.
+    pub protocol_hash: u64,
+    pub transport: Transport,
+    /// Port of the game server, on the address the beacon was sent from.
+    pub port: u16,
+}
+
+impl ServerBeacon {
+    pub fn encode(&self) -> Vec<u8> {
+        ron::to_string(self).unwrap_or_default().into_bytes()
+    }
+
+    pub fn decode(bytes: &[u8]) -> Option<Self> {
+        ron::de::from_bytes(bytes).ok()
+    }
+}
+
+/// Non-blocking socket sending beacons to every `targets` address.
+pub struct BeaconSender {
+    socket: UdpSocket,
+    targets: Vec<SocketAddr>,
+}
+
+impl BeaconSender {
+    pub fn new(targets: Vec<SocketAddr>) -> io::Result<Self> {
+        let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0))?;
+        socket.set_broadcast(true)?;
+        socket.set_nonblocking(true)?;
+        Ok(Self { socket, targets })
+    }
+
+    /// Broadcast on the local network and on loopback.
+    pub fn lan() -> io::Result<Self> {
+        Self::new(vec![
+            SocketAddr::from((Ipv4Addr::BROADCAST, DISCOVERY_PORT)),
+            SocketAddr::from((Ipv4Addr::LOCALHOST, DISCOVERY_PORT)),
+        ])
+    }
+
+    pub fn send(&self, beacon: &ServerBeacon) {
+        let bytes = beacon.encode();
+        for target in &self.targets {
+            if let Err(e) = self.socket.send_to(&bytes, target) {
+                debug!("Failed to send beacon to {target}: {e}");
+            }
+        }
+    }
+}
+
+/// Non-blocking socket receiving beacons.
+pub struct BeaconListener {
+    socket: UdpSocket,
+}
+
+impl BeaconListener {
+    pub fn bind(addr: SocketAddr) -> io::Result<Self> {
+        let socket = UdpSocket::bind(addr)?;
+        socket.set_nonblocking(true)?;
+        Ok(Self { socket })
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.socket.local_addr()
+    }
+
+    /// Beacons received since the last call, with the address of the game server.
+    pub fn receive(&self) -> Vec<(SocketAddr, ServerBeacon)> {
+        let mut beacons = Vec::new();
+        let mut buffer = [0; MAX_BEACON_SIZE];
+
+        loop {
+            match self.socket.recv_from(&mut buffer) {
+                Ok((len, from)) => {
+                    if let Some(beacon) = ServerBeacon::decode(&buffer[..len]) {
+                        beacons.push((SocketAddr::new(from.ip(), beacon.port), beacon));
+                    }
+                }
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => break,
+                Err(e) => {
+                    debug!("Failed to receive beacon: {e}");
+                    break;
+                }
+            }
+        }
+
+        beacons
+    }
+}
+
+/// Information about this server advertised in its beacon.
+#[derive(Resource)]
+pub struct ServerInfo {
+    pub name: String,
+    /// Port of the game server.
+    pub port: u16,
+}
+
+#[derive(Resource)]
+struct BeaconTimer(Timer);
+
+pub struct ServerDiscoveryPlugin;
+
+impl Plugin for ServerDiscoveryPlugin {
+    fn build(&self, app: &mut App) {
+        match BeaconSender::lan() {
+            Ok(sender) => {
+                app.insert_non_send_resource(sender)
+                    .insert_resource(BeaconTimer(Timer::new(
+                        BEACON_INTERVAL,
+                        TimerMode::Repeating,
+                    )))
+                    .add_systems(Update, send_beacon);
+            }
+            Err(e) => error!("LAN discovery disabled, failed to open the beacon socket: {e}"),
+        }
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn send_beacon(
+    time: Res<Time>,
+    mut timer: ResMut<BeaconTimer>,
+    sender: NonSend<BeaconSender>,
+    server_info: Res<ServerInfo>,
+    transport: Res<Transport>,
+    protocol_hash: Res<ProtocolHash>,
+    current_level: Res<CurrentLevel>,
+    player_q: Query<(), (With<Player>, With<Replicate>)>,
+) {
+    if !timer.0.tick(time.delta()).just_finished() {
+        return;
+    }
+
+    sender.send(&ServerBeacon {
+        name: server_info.name.clone(),
+        map: current_level.name.clone(),
+        players: player_q.iter().count() as u32,
+        protocol_hash: protocol_hash.0,
+        transport: *transport,
+        port: server_info.port,
+    });
+}
+
+/// Menu listing the servers found on the local network.
+///
+/// Joining a server spawns the client with `options`, pointed at that server.
+#[derive(Resource)]
+pub struct ServerBrowser {
+    pub options: CliClientOptions,
+}
+
+/// Servers found on the local network, with the time their last beacon was received.
+#[derive(Resource, Default)]
+struct DiscoveredServers(HashMap<SocketAddr, (ServerBeacon, Duration)>);
+
+pub struct ServerBrowserPlugin;
+
+impl Plugin for ServerBrowserPlugin {
+    fn build(&self, app: &mut App) {
+        match BeaconListener::bind(SocketAddr::from((Ipv4Addr::UNSPECIFIED, DISCOVERY_PORT))) {
+            Ok(listener) => add_server_browser(app, listener),
+            Err(e) => {
+                let error = format!("Failed to listen for servers on port {DISCOVERY_PORT}: {e}");
+                error!("{error}, joining the server given on the command line");
+                app.insert_resource(DiscoveryError(error))
+                    .add_systems(Startup, join_without_browser);
+            }
+        }
+    }
+}
+
+/// Show the servers heard by `listener` in the browser.
+pub(crate) fn add_server_browser(app: &mut App, listener: BeaconListener) {
+    app.insert_non_send_resource(listener)
+        .init_resource::<DiscoveredServers>()
+        .add_systems(Startup, spawn_server_browser)
+        .add_systems(
+            Update,
+            (receive_beacons, update_server_browser, join_server)
+                .chain()
+                .run_if(resource_exists::<ServerBrowser>),
+        );
+}
+
+/// Why servers can't be discovered.
+#[derive(Resource)]
+struct DiscoveryError(String);
+
+#[derive(Component)]
+struct ServerBrowserMenu;
+
+#[derive(Component)]
+struct ServerList;
+
+/// Button joining the server at this address.
+#[derive(Component)]
+pub(crate) struct JoinButton(pub SocketAddr);
+
+fn spawn_server_browser(mut commands: Commands) {
+    commands
+        .spawn((
+            Name::new("Server browser"),
+            ServerBrowserMenu,
+            Node {
+                width: Val::Percent(100.),
+                height: Val::Percent(100.),
+                flex_direction: FlexDirection::Column,
+                align_items: AlignItems::Center,
+                padding: UiRect::top(Val::Px(40.)),
+                row_gap: Val::Px(8.),
+                ..default()
+            },
+            BackgroundColor(Color::srgb(0.1, 0.1, 0.1)),
+        ))
+        .with_children(|parent| {
+            parent.spawn((
+                Text::new("Servers on the local network"),
+                TextFont {
+                    font_size: 24.,
+                    ..default()
+                },
+            ));
+            parent.spawn((
+                ServerList,
+                Node {
+                    flex_direction: FlexDirection::Column,
+                    row_gap: Val::Px(4.),
+                    ..default()
+                },
+            ));
+        });
+}
+
+/// Join the server given on the command line, showing why the browser isn't available.
+fn join_without_browser(
+    mut commands: Commands,
+    browser: Res<ServerBrowser>,
+    error: Res<DiscoveryError>,
+) {
+    info!("Joining server {}", browser.options.server_addr);
+    commands.spawn(browser.options.clone());
+    commands.remove_resource::<ServerBrowser>();
+
+    commands.spawn((
+        Name::new("Discovery error"),
+        Text::new(format!(
+            "{}\nJoined {} instead.",
+            error.0, browser.options.server_addr
+        )),
+        TextColor(Color::srgb(1., 0.4, 0.4)),
+        TextFont {
+            font_size: 14.,
+            ..default()
+        },
+        Node {
+            position_type: PositionType::Absolute,
+            bottom: Val::Px(8.),
+            left: Val::Px(8.),
+            ..default()
+        },
+    ));
+}
+
+fn receive_beacons(
+    time: Res<Time<Real>>,
+    listener: NonSend<BeaconListener>,
+    transport: Res<Transport>,
+    protocol_hash: Res<ProtocolHash>,
+    mut servers: ResMut<DiscoveredServers>,
+) {
+    let now = time.elapsed();
+    // beacons are received every second, only rebuild the browser when a server changes
+    let mut changed = false;
+    let known_servers = &mut servers.bypass_change_detection().0;
+
+    for (mut addr, beacon) in listener.receive() {
+        // the client could not connect to servers of another protocol or transport
+        if beacon.protocol_hash != protocol_hash.0 || beacon.transport != *transport {
+            continue;
+        }
+
+        // a server on this machine is heard both on loopback and on the LAN, keep loopback
+        let duplicate = known_servers
+            .iter()
+            .find(|(known, (known_beacon, _))| {
+                known.ip() != addr.ip()
+                    && known.port() == addr.port()
+                    && known_beacon.name == beacon.name
+            })
+            .map(|(known, _)| *known);
+        match duplicate {
+            Some(known) if known.ip().is_loopback() => addr = known,
+            Some(known) if addr.ip().is_loopback() => {
+                known_servers.remove(&known);
+                changed = true;
+            }
+            _ => {}
+        }
+
+        match known_servers.insert(addr, (beacon.clone(), now)) {
+            Some((previous, _)) if previous == beacon => {}
+            _ => changed = true,
+        }
+    }
+
+    let before = known_servers.len();
+    known_servers.retain(|_, (_, last_seen)| now.saturating_sub(*last_seen) < SERVER_TIMEOUT);
+    if changed || known_servers.len() != before {
+        servers.set_changed();
+    }
+}
+
+fn update_server_browser(
+    mut commands: Commands,
+    servers: Res<DiscoveredServers>,
+    server_list: Single<Entity, With<ServerList>>,
+) {
+    if !servers.is_changed() {
+        return;
+    }
+
+    let mut servers: Vec<_> = servers.0.iter().collect();
+    servers.sort_by(|(_, (a, _)), (_, (b, _))| a.name.cmp(&b.name));
+
+    commands
+        .entity(*server_list)
+        .despawn_related::<Children>()
+        .with_children(|parent| {
+            if servers.is_empty() {
+                parent.spawn(Text::new("Searching..."));
+            }
+
+            for (addr, (beacon, _)) in servers {
+                parent
+                    .spawn((
+                        JoinButton(*addr),
+                        Button,
+                        Node {
+                            padding: UiRect::axes(Val::Px(12.), Val::Px(6.)),
+                            ..default()
+                        },
+                        BackgroundColor(Color::srgb(0.25, 0.25, 0.3)),
+                    ))
+                    .with_child(Text::new(format!(
+                        "{} - {} - {} players ({addr})",
+                        beacon.name, beacon.map, beacon.players
+                    )));
+            }
+        });
+}
+
+fn join_server(
+    mut commands: Commands,
+    button_q: Query<(&Interaction, &JoinButton), Changed<Interaction>>,
+    browser: Res<ServerBrowser>,
+    menu: Single<Entity, With<ServerBrowserMenu>>,
+) {
+    for (interaction, JoinButton(addr)) in button_q.iter() {
+        if *interaction != Interaction::Pressed {
+            continue;
+        }
+
+        info!("Joining server {addr}");
+        commands.spawn(CliClientOptions {
+            server_addr: *addr,
+            ..browser.options.clone()
+        });
+        commands.entity(*menu).despawn();
+        commands.remove_resource::<ServerBrowser>();
+        return;
+    }
+}
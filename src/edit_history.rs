@@ -0,0 +1,319 @@
+//! Undo and redo of the edits made in the editor: inspector, gizmo and level edits.
+//!
+//! Each [`Edit`] keeps the values to restore. Restoring them records the values they replace,
+//! which makes the opposite edit, so the same code undoes and redoes.
+
+use std::any::TypeId;
+
+use bevy::{
+    ecs::component::Tick,
+    prelude::*,
+    reflect::{PartialReflect, TypeRegistry},
+    window::PrimaryWindow,
+};
+use bevy_inspector_egui::bevy_egui::EguiContext;
+
+use crate::{
+    editor::UiState,
+    level::{CurrentLevel, Level},
+};
+
+/// Number of edits which can be undone.
+const MAX_HISTORY: usize = 100;
+
+/// Value to restore.
+pub enum Change {
+    Component {
+        entity: Entity,
+        type_id: TypeId,
+        value: Box<dyn PartialReflect>,
+    },
+    Resource {
+        type_id: TypeId,
+        value: Box<dyn PartialReflect>,
+    },
+    /// Walls, balls and spawn points of the level named `name`.
+    Level { name: String, level: Level },
+}
+
+impl Change {
+    /// Clone the current value of the component of `entity`.
+    pub fn component(
+        world: &World,
+        registry: &TypeRegistry,
+        entity: Entity,
+        type_id: TypeId,
+    ) -> Option<Self> {
+        let reflect_component = registry.get_type_data::<ReflectComponent>(type_id)?;
+        let value = reflect_component.reflect(world.get_entity(entity).ok()?)?;
+        Some(Change::Component {
+            entity,
+            type_id,
+            value: value.to_dynamic(),
+        })
+    }
+
+    /// Clone the current value of a resource.
+    pub fn resource(world: &World, registry: &TypeRegistry, type_id: TypeId) -> Option<Self> {
+        let reflect_resource = registry.get_type_data::<ReflectResource>(type_id)?;
+        let value = reflect_resource.reflect(world).ok()?;
+        Some(Change::Resource {
+            type_id,
+            value: value.to_dynamic(),
+        })
+    }
+
+    /// Whether the current value was changed since `last_run`.
+    fn is_changed(&self, world: &World, last_run: Tick) -> bool {
+        let ticks = match self {
+            Change::Component {
+                entity, type_id, ..
+            } => world
+                .components()
+                .get_id(*type_id)
+                .and_then(|component_id| {
+                    world
+                        .get_entity(*entity)
+                        .ok()?
+                        .get_change_ticks_by_id(component_id)
+                }),
+            Change::Resource { type_id, .. } => world
+                .components()
+                .get_resource_id(*type_id)
+                .and_then(|component_id| world.get_resource_change_ticks_by_id(component_id)),
+            Change::Level { .. } => None,
+        };
+        ticks.is_some_and(|ticks| ticks.is_changed(last_run, world.read_change_tick()))
+    }
+
+    /// Restore the value, and return the change which restores the replaced value.
+    ///
+    /// Returns `None` when the value can't be restored anymore, e.g. for a despawned entity.
+    fn restore(self, world: &mut World, registry: &TypeRegistry) -> Option<Change> {
+        match self {
+            Change::Component {
+                entity,
+                type_id,
+                value,
+            } => {
+                let current = Change::component(world, registry, entity, type_id)?;
+                let reflect_component = registry.get_type_data::<ReflectComponent>(type_id)?;
+                reflect_component.apply(world.get_entity_mut(entity).ok()?, value.as_ref());
+                Some(current)
+            }
+            Change::Resource { type_id, value } => {
+                let current = Change::resource(world, registry, type_id)?;
+                let reflect_resource = registry.get_type_data::<ReflectResource>(type_id)?;
+                reflect_resource.apply(world, value.as_ref());
+                Some(current)
+            }
+            Change::Level { name, level } => {
+                // edits of another level are forgotten once it is unloaded
+                if world.resource::<CurrentLevel>().name != name {
+                    return None;
+                }
+                let current = Level::from_world(world);
+                // reloading the level respawns its entities, and sends it to clients
+                world.resource_mut::<CurrentLevel>().level = level;
+                Some(Change::Level {
+                    name,
+                    level: current,
+                })
+            }
+        }
+    }
+}
+
+/// Changes made together, undone together.
+pub struct Edit(pub Vec<Change>);
+
+impl Edit {
+    /// Restore every change, and return the edit which restores the replaced values.
+    fn restore(self, world: &mut World) -> Option<Edit> {
+        let registry = world.resource::<AppTypeRegistry>().clone();
+        let registry = registry.read();
+
+        let mut inverse: Vec<Change> = self
+            .0
+            .into_iter()
+            .rev()
+            .filter_map(|change| change.restore(world, &registry))
+            .collect();
+        inverse.reverse();
+
+        (!inverse.is_empty()).then_some(Edit(inverse))
+    }
+}
+
+#[derive(Default)]
+pub struct EditHistory {
+    undo: Vec<Edit>,
+    redo: Vec<Edit>,
+    /// Values from before the edit being made in the inspector.
+    inspector_edit: Option<InspectorSnapshot>,
+}
+
+impl EditHistory {
+    /// Remember the values from before an edit.
+    pub fn record(&mut self, edit: Edit) {
+        if edit.0.is_empty() {
+            return;
+        }
+        if self.undo.len() >= MAX_HISTORY {
+            self.undo.remove(0);
+        }
+        self.undo.push(edit);
+        self.redo.clear();
+    }
+
+    /// Start an edit in the inspector, from the values it can change.
+    pub fn begin_inspector_edit(&mut self, snapshot: InspectorSnapshot) {
+        self.end_inspector_edit();
+        self.inspector_edit = Some(snapshot);
+    }
+
+    /// Remember the values changed by the inspector, shown since `last_run`.
+    pub fn inspector_shown(&mut self, world: &World, last_run: Tick) {
+        if let Some(snapshot) = &mut self.inspector_edit {
+            snapshot.mark_edited(world, last_run);
+        }
+    }
+
+    /// Record the edit made in the inspector, if it changed any value.
+    pub fn end_inspector_edit(&mut self) {
+        if let Some(snapshot) = self.inspector_edit.take() {
+            self.record(snapshot.into_edit());
+        }
+    }
+
+    pub fn can_undo(&self) -> bool {
+        !self.undo.is_empty()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        !self.redo.is_empty()
+    }
+
+    pub fn undo(&mut self, world: &mut World) {
+        self.end_inspector_edit();
+        while let Some(edit) = self.undo.pop() {
+            if let Some(inverse) = edit.restore(world) {
+                self.redo.push(inverse);
+                return;
+            }
+        }
+    }
+
+    pub fn redo(&mut self, world: &mut World) {
+        self.end_inspector_edit();
+        while let Some(edit) = self.redo.pop() {
+            if let Some(inverse) = edit.restore(world) {
+                self.undo.push(inverse);
+                return;
+            }
+        }
+    }
+}
+
+/// Values of the components of `entities`, or of a resource, when an edit starts in the
+/// inspector, to record the ones it changes.
+///
+/// The inspector only marks the values it changes as changed, so the values changed while it
+/// is shown are the edited ones, and not the ones changed by the game during the edit.
+pub struct InspectorSnapshot {
+    values: Vec<Change>,
+    /// Whether the inspector changed each value.
+    edited: Vec<bool>,
+}
+
+impl InspectorSnapshot {
+    fn new(values: Vec<Change>) -> Self {
+        Self {
+            edited: vec![false; values.len()],
+            values,
+        }
+    }
+
+    /// Every reflected component of `entities` and their descendants.
+    pub fn entities(world: &World, registry: &TypeRegistry, entities: &[Entity]) -> Self {
+        let mut values = Vec::new();
+        let mut stack = entities.to_vec();
+        while let Some(entity) = stack.pop() {
+            let Ok(entity_ref) = world.get_entity(entity) else {
+                continue;
+            };
+            for component_id in entity_ref.archetype().components() {
+                let type_id = world
+                    .components()
+                    .get_info(component_id)
+                    .and_then(|info| info.type_id());
+                if let Some(value) =
+                    type_id.and_then(|type_id| Change::component(world, registry, entity, type_id))
+                {
+                    values.push(value);
+                }
+            }
+            if let Some(children) = entity_ref.get::<Children>() {
+                stack.extend(children.iter());
+            }
+        }
+        Self::new(values)
+    }
+
+    pub fn resource(world: &World, registry: &TypeRegistry, type_id: TypeId) -> Self {
+        Self::new(
+            Change::resource(world, registry, type_id)
+                .into_iter()
+                .collect(),
+        )
+    }
+
+    /// Mark the values changed since `last_run`, the tick before the inspector was shown.
+    fn mark_edited(&mut self, world: &World, last_run: Tick) {
+        for (value, edited) in self.values.iter().zip(&mut self.edited) {
+            *edited |= value.is_changed(world, last_run);
+        }
+    }
+
+    /// The values from before the edit of the ones the inspector changed.
+    fn into_edit(self) -> Edit {
+        Edit(
+            self.values
+                .into_iter()
+                .zip(self.edited)
+                .filter_map(|(value, edited)| edited.then_some(value))
+                .collect(),
+        )
+    }
+}
+
+/// Ctrl+Z to undo the last edit made in the editor, Ctrl+Y to redo it.
+pub fn edit_history_shortcuts(world: &mut World) {
+    let keyboard = world.resource::<ButtonInput<KeyCode>>();
+    if !keyboard.any_pressed([KeyCode::ControlLeft, KeyCode::ControlRight]) {
+        return;
+    }
+    let undo = keyboard.just_pressed(KeyCode::KeyZ);
+    let redo = keyboard.just_pressed(KeyCode::KeyY);
+    if !undo && !redo {
+        return;
+    }
+
+    // text fields have their own undo
+    let wants_keyboard = world
+        .query_filtered::<&mut EguiContext, With<PrimaryWindow>>()
+        .single_mut(world)
+        .is_ok_and(|mut context| context.get_mut().wants_keyboard_input());
+    if wants_keyboard {
+        return;
+    }
+
+    world.resource_scope::<UiState, _>(|world, mut ui_state| {
+        let history = ui_state.history_mut();
+        if undo {
+            history.undo(world);
+        } else {
+            history.redo(world);
+        }
+    });
+}
@@ -1,5 +1,6 @@
 use std::any::TypeId;
 
+use avian2d::prelude::{Collider, Rotation};
 use bevy::{
     asset::{ReflectAsset, UntypedAssetId},
     prelude::*,
@@ -12,12 +13,36 @@ use bevy_inspector_egui::{
     bevy_egui::{self, EguiContext, EguiContextPass, EguiContextSettings},
     bevy_inspector::{
         self,
-        hierarchy::{SelectedEntities, hierarchy_ui},
+        hierarchy::{Hierarchy, SelectedEntities},
         ui_for_entities_shared_components, ui_for_entity_with_children,
     },
 };
 
 use egui_dock::{DockArea, DockState, NodeIndex, Style, egui};
+use leafwing_input_manager::{plugin::InputManagerSystem, prelude::ActionState};
+use lightyear::prelude::{Controlled, Predicted};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    chat::{ChatInput, chat_input_open},
+    config,
+    edit_history::{EditHistory, InspectorSnapshot, edit_history_shortcuts},
+    hierarchy_menu::hierarchy_context_menu,
+    level_editor::{LevelEditorPlugin, level_editor_ui},
+    network_inspector::network_ui,
+    protocol::PlayerAction,
+    transform_gizmo::{TransformGizmoPlugin, transform_gizmo_toolbar},
+};
+
+const SELECTION_COLOR: Color = Color::srgb(1.0, 0.8, 0.0);
+
+/// Space between the outline of selected entities and their collider, in pixels.
+const SELECTION_MARGIN: f32 = 4.;
+
+const EDITOR_SETTINGS_FILE: &str = "editor.ron";
+
+/// Configuration file in which the layout of the tabs is kept between sessions.
+const LAYOUT_FILE: &str = "editor_layout.ron";
 
 pub struct EditorPlugin;
 
@@ -27,86 +52,195 @@ impl Plugin for EditorPlugin {
             .add_plugins(bevy_egui::EguiPlugin {
                 enable_multipass_for_primary_context: true,
             })
-            .add_plugins(DefaultInspectorConfigPlugin)
-            // .add_plugins(bevy_mod_picking::plugins::DefaultPickingPlugins)
-            .add_systems(Startup, setup_editor_camera)
-            .add_systems(Update, toggle_editor)
+            .add_plugins((
+                DefaultInspectorConfigPlugin,
+                TransformGizmoPlugin,
+                LevelEditorPlugin,
+            ))
+            .insert_resource(config::load_or_default::<EditorSettings>(
+                EDITOR_SETTINGS_FILE,
+            ))
+            .add_systems(Update, toggle_editor.run_if(not(chat_input_open)))
+            // after the actions are read from the keyboard, before lightyear buffers them in
+            // FixedPreUpdate to send them to the server
+            .add_systems(
+                PreUpdate,
+                isolate_gameplay_input.after(InputManagerSystem::Update),
+            )
             .add_systems(EguiContextPass, show_ui_system)
-            .add_systems(PostUpdate, set_camera_viewport.after(show_ui_system))
-            // .add_systems(Update, auto_add_raycast_target)
-            // .add_systems(Update, handle_pick_events)
+            .add_systems(
+                PostUpdate,
+                (
+                    set_camera_viewport.after(show_ui_system),
+                    reset_camera_viewport.run_if(resource_removed::<UiState>),
+                ),
+            )
+            .add_systems(
+                Update,
+                (
+                    draw_selection_outline,
+                    edit_history_shortcuts
+                        .run_if(resource_exists::<UiState>.and(not(chat_input_open))),
+                ),
+            )
+            .add_systems(Last, save_layout_on_exit)
+            .add_observer(select_clicked_entity)
             .register_type::<Option<Handle<Image>>>()
             .register_type::<AlphaMode>();
     }
 }
 
-/// User should add this camera on their own camera to add additional settings and mitigate different specifics of games.
+/// Camera of the game, rendered in the GameView tab while the editor is open.
+///
+/// User should add this component on their own camera to add additional settings and mitigate different specifics of games.
 #[derive(Component)]
-struct EditorCamera;
-
-fn setup_editor_camera(mut commands: Commands) {
-    let camera = Camera {
-        order: 900, // TOOD: should on top of everything.
-        is_active: false,
-        ..default()
-    };
+pub struct EditorCamera;
+
+/// Loaded from `config/editor.ron`.
+#[derive(Resource, Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct EditorSettings {
+    /// Key which opens and closes the editor.
+    pub toggle_key: KeyCode,
+}
 
-    commands.spawn((
-        Name::new("Editor camera"),
-        Camera3d::default(),
-        camera,
-        EditorCamera,
-    ));
+impl Default for EditorSettings {
+    fn default() -> Self {
+        Self {
+            toggle_key: KeyCode::F1,
+        }
+    }
 }
 
-/// Disables all other cameras except the editor one.
+/// Open or close the editor. While it is open, the game is rendered in the GameView tab.
 fn toggle_editor(
     mut commands: Commands,
     keyboard: Res<ButtonInput<KeyCode>>,
-    ui_state: Option<ResMut<UiState>>,
+    settings: Res<EditorSettings>,
+    ui_state: Option<Res<UiState>>,
 ) {
-    if keyboard.just_pressed(KeyCode::KeyE) {
+    if keyboard.just_pressed(settings.toggle_key) {
         match ui_state {
-            Some(_) => commands.remove_resource::<UiState>(),
+            Some(ui_state) => {
+                ui_state.save_layout();
+                commands.remove_resource::<UiState>();
+            }
             None => commands.insert_resource(UiState::new()),
         }
     }
 }
 
-/*
-fn auto_add_raycast_target(
-    mut commands: Commands,
-    query: Query<Entity, (Without<PickRaycastTarget>, With<Handle<Mesh>>)>,
+/// Disable the gameplay input of the controlled player while egui has the keyboard focus, e.g.
+/// while typing in the inspector, or while a chat message is typed.
+fn isolate_gameplay_input(
+    mut egui_context: Single<&mut EguiContext, With<PrimaryWindow>>,
+    chat_input: Option<Res<ChatInput>>,
+    mut action_state_q: Query<&mut ActionState<PlayerAction>, (With<Predicted>, With<Controlled>)>,
+) {
+    let suppressed = chat_input_open(chat_input) || egui_context.get_mut().wants_keyboard_input();
+
+    for mut action_state in action_state_q.iter_mut() {
+        if suppressed && !action_state.disabled() {
+            // actions held when typing starts would stay pressed
+            action_state.release_all();
+            action_state.disable_all();
+        } else if !suppressed && action_state.disabled() {
+            action_state.enable_all();
+        }
+    }
+}
+
+/// Keep the layout when the game is closed with the editor open.
+fn save_layout_on_exit(mut exit_events: EventReader<AppExit>, ui_state: Option<Res<UiState>>) {
+    if exit_events.read().next().is_none() {
+        return;
+    }
+    if let Some(ui_state) = ui_state {
+        ui_state.save_layout();
+    }
+}
+
+/// Select the entity clicked in the GameView tab, ctrl or shift to add it to the selection.
+///
+/// Sprites are pickable through Bevy's sprite picking backend. Clicks on egui tabs are ignored
+/// because the game camera only renders, and so only picks, inside the GameView tab.
+fn select_clicked_entity(
+    mut trigger: Trigger<Pointer<Click>>,
+    ui_state: Option<ResMut<UiState>>,
+    keyboard: Res<ButtonInput<KeyCode>>,
 ) {
-    for entity in &query {
-        commands
-            .entity(entity)
-            .insert((PickRaycastTarget::default(), PickableBundle::default()));
+    let Some(mut ui_state) = ui_state else {
+        return;
+    };
+    if trigger.event().button != PointerButton::Primary {
+        return;
+    }
+    // select the clicked entity, not its parents
+    trigger.propagate(false);
+
+    let add = keyboard.any_pressed([
+        KeyCode::ControlLeft,
+        KeyCode::ControlRight,
+        KeyCode::ShiftLeft,
+        KeyCode::ShiftRight,
+    ]);
+    ui_state.select(trigger.target(), add);
+}
+
+/// Position of the cursor in the world, if it is inside the GameView tab.
+pub(crate) fn cursor_world_position(
+    window: &Window,
+    camera: &Camera,
+    camera_transform: &GlobalTransform,
+) -> Option<Vec2> {
+    let cursor = window.cursor_position()?;
+    let viewport = camera.logical_viewport_rect()?;
+    if !viewport.contains(cursor) {
+        return None;
     }
+    camera
+        .viewport_to_world_2d(camera_transform, cursor - viewport.min)
+        .ok()
 }
 
-fn handle_pick_events(
-    mut ui_state: ResMut<UiState>,
-    mut click_events: EventReader<PointerClick>,
-    mut egui: ResMut<EguiContext>,
-    egui_entity: Query<&EguiPointer>,
+/// Outline the colliders of the selected entities.
+fn draw_selection_outline(
+    ui_state: Option<Res<UiState>>,
+    entity_q: Query<(&GlobalTransform, Option<&Collider>)>,
+    mut gizmos: Gizmos,
 ) {
-    let egui_context = egui.ctx_mut();
+    let Some(ui_state) = ui_state else {
+        return;
+    };
 
-    for click in click_events.iter() {
-        if egui_entity.get(click.target()).is_ok() {
+    for entity in ui_state.selected_entities.iter() {
+        let Ok((transform, collider)) = entity_q.get(entity) else {
             continue;
         };
-
-        let modifiers = egui_context.input().modifiers;
-        let add = modifiers.ctrl || modifiers.shift;
-
-        ui_state
-            .selected_entities
-            .select_maybe_add(click.target(), add);
+        let (_, rotation, translation) = transform.to_scale_rotation_translation();
+        let position = translation.truncate();
+
+        match collider {
+            Some(collider) => {
+                let angle = rotation.to_euler(EulerRot::XYZ).2;
+                let aabb = collider.aabb(position, Rotation::radians(angle));
+                let size = aabb.max - aabb.min + Vec2::splat(SELECTION_MARGIN * 2.);
+                gizmos.rect_2d(
+                    Isometry2d::from_translation((aabb.min + aabb.max) / 2.),
+                    size,
+                    SELECTION_COLOR,
+                );
+            }
+            None => {
+                gizmos.circle_2d(
+                    Isometry2d::from_translation(position),
+                    SELECTION_MARGIN,
+                    SELECTION_COLOR,
+                );
+            }
+        }
     }
 }
-*/
 
 fn show_ui_system(world: &mut World) {
     if !world.contains_resource::<UiState>() {
@@ -157,6 +291,10 @@ fn set_camera_viewport(
     // past the size of the render target, i.e. the physical window in our case.
     // Typically this shouldn't happen- but during init and resizing etc. edge cases might occur.
     // Simply do nothing in those cases.
+    // the GameView tab is not laid out yet on the first frame
+    if physical_size.cmpeq(UVec2::ZERO).any() {
+        return;
+    }
     if rect.x <= window_size.x && rect.y <= window_size.y {
         cam.viewport = Some(Viewport {
             physical_position,
@@ -166,6 +304,11 @@ fn set_camera_viewport(
     }
 }
 
+/// Render the game to the whole window again once the editor is closed.
+fn reset_camera_viewport(mut cam: Single<&mut Camera, With<EditorCamera>>) {
+    cam.viewport = None;
+}
+
 #[derive(Eq, PartialEq)]
 enum InspectorSelection {
     Entities,
@@ -174,37 +317,69 @@ enum InspectorSelection {
 }
 
 #[derive(Resource)]
-struct UiState {
+pub(crate) struct UiState {
     state: DockState<EguiWindow>,
     viewport_rect: egui::Rect,
     selected_entities: SelectedEntities,
     selection: InspectorSelection,
+    history: EditHistory,
 }
 
 impl UiState {
+    /// Open the editor with the layout saved when it was last closed.
     pub fn new() -> Self {
-        let mut state = DockState::new(vec![EguiWindow::GameView]);
-        let tree = state.main_surface_mut();
-        let [game, _inspector] =
-            tree.split_right(NodeIndex::root(), 0.75, vec![EguiWindow::Inspector]);
-        let [game, _hierarchy] = tree.split_left(game, 0.2, vec![EguiWindow::Hierarchy]);
-        let [_game, _bottom] =
-            tree.split_below(game, 0.8, vec![EguiWindow::Resources, EguiWindow::Assets]);
-
         Self {
-            state,
+            state: config::load_or_default::<EditorLayout>(LAYOUT_FILE).dock,
             selected_entities: SelectedEntities::default(),
             selection: InspectorSelection::Entities,
             viewport_rect: egui::Rect::NOTHING,
+            history: EditHistory::default(),
         }
     }
 
+    /// Select `entity` in the inspector, or add it to the selection.
+    pub(crate) fn select(&mut self, entity: Entity, add: bool) {
+        self.selected_entities.select_maybe_add(entity, add);
+        self.selection = InspectorSelection::Entities;
+    }
+
+    /// The selected entity, if exactly one is selected.
+    pub(crate) fn selected_entity(&self) -> Option<Entity> {
+        single_entity(&self.selected_entities)
+    }
+
+    /// Undo and redo history of the edits made while the editor is open.
+    pub(crate) fn history_mut(&mut self) -> &mut EditHistory {
+        &mut self.history
+    }
+
+    fn save_layout(&self) {
+        config::save(
+            LAYOUT_FILE,
+            &EditorLayout {
+                dock: self.state.clone(),
+            },
+        );
+    }
+
     fn ui(&mut self, world: &mut World, ctx: &mut egui::Context) {
+        egui::TopBottomPanel::top("editor_menu").show(ctx, |ui| {
+            egui::menu::bar(ui, |ui| {
+                ui.menu_button("Layout", |ui| {
+                    if ui.button("Reset layout").clicked() {
+                        self.state = EditorLayout::default().dock;
+                        ui.close_menu();
+                    }
+                });
+            });
+        });
+
         let mut tab_viewer = TabViewer {
             world,
             viewport_rect: &mut self.viewport_rect,
             selected_entities: &mut self.selected_entities,
             selection: &mut self.selection,
+            history: &mut self.history,
         };
         DockArea::new(&mut self.state)
             .style(Style::from_egui(ctx.style().as_ref()))
@@ -212,13 +387,47 @@ impl UiState {
     }
 }
 
-#[derive(Debug)]
+/// Arrangement of the tabs, with the tab shown in each split.
+#[derive(Serialize, Deserialize)]
+struct EditorLayout {
+    dock: DockState<EguiWindow>,
+}
+
+impl Default for EditorLayout {
+    fn default() -> Self {
+        let mut dock = DockState::new(vec![EguiWindow::GameView]);
+        let tree = dock.main_surface_mut();
+        let [game, _inspector] = tree.split_right(
+            NodeIndex::root(),
+            0.75,
+            vec![EguiWindow::Inspector, EguiWindow::Network],
+        );
+        let [game, _hierarchy] = tree.split_left(game, 0.2, vec![EguiWindow::Hierarchy]);
+        let [_game, _bottom] = tree.split_below(
+            game,
+            0.8,
+            vec![EguiWindow::Resources, EguiWindow::Assets, EguiWindow::Level],
+        );
+        Self { dock }
+    }
+}
+
+fn single_entity(selected_entities: &SelectedEntities) -> Option<Entity> {
+    match selected_entities.as_slice() {
+        &[entity] => Some(entity),
+        _ => None,
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 enum EguiWindow {
     GameView,
     Hierarchy,
     Resources,
     Assets,
     Inspector,
+    Level,
+    Network,
 }
 
 struct TabViewer<'a> {
@@ -226,6 +435,7 @@ struct TabViewer<'a> {
     selected_entities: &'a mut SelectedEntities,
     selection: &'a mut InspectorSelection,
     viewport_rect: &'a mut egui::Rect,
+    history: &'a mut EditHistory,
 }
 
 impl egui_dock::TabViewer for TabViewer<'_> {
@@ -238,41 +448,64 @@ impl egui_dock::TabViewer for TabViewer<'_> {
         match window {
             EguiWindow::GameView => {
                 *self.viewport_rect = ui.clip_rect();
+                let selected = single_entity(self.selected_entities);
+                transform_gizmo_toolbar(ui, self.world, selected);
             }
             EguiWindow::Hierarchy => {
-                let selected = hierarchy_ui(self.world, ui, self.selected_entities);
+                let selected = Hierarchy {
+                    world: self.world,
+                    selected: self.selected_entities,
+                    context_menu: Some(&mut hierarchy_context_menu),
+                    shortcircuit_entity: None,
+                    extra_state: self.history,
+                }
+                .show::<()>(ui);
                 if selected {
                     *self.selection = InspectorSelection::Entities;
                 }
             }
             EguiWindow::Resources => select_resource(ui, &type_registry, self.selection),
             EguiWindow::Assets => select_asset(ui, &type_registry, self.world, self.selection),
-            EguiWindow::Inspector => match *self.selection {
-                InspectorSelection::Entities => match self.selected_entities.as_slice() {
-                    &[entity] => ui_for_entity_with_children(self.world, entity, ui),
-                    entities => ui_for_entities_shared_components(self.world, entities, ui),
-                },
-                InspectorSelection::Resource(type_id, ref name) => {
-                    ui.label(name);
-                    bevy_inspector::by_type_id::ui_for_resource(
-                        self.world,
-                        type_id,
-                        ui,
-                        name,
-                        &type_registry,
-                    )
+            EguiWindow::Level => {
+                let selected = single_entity(self.selected_entities);
+                level_editor_ui(ui, self.world, selected, self.history);
+            }
+            EguiWindow::Network => {
+                if let Some(entity) = network_ui(ui, self.world) {
+                    self.selected_entities.select_maybe_add(entity, false);
+                    *self.selection = InspectorSelection::Entities;
+                }
+            }
+            EguiWindow::Inspector => {
+                // an edit lasts from a press in the inspector until the pointer is released and no
+                // widget has the keyboard focus, so that dragging a value makes a single edit
+                let (pressed, released) =
+                    ui.input(|input| (input.pointer.any_pressed(), !input.pointer.any_down()));
+                let focused = ui.memory(|memory| memory.focused().is_some());
+                if pressed || (released && !focused) {
+                    self.history.end_inspector_edit();
                 }
-                InspectorSelection::Asset(type_id, ref name, handle) => {
-                    ui.label(name);
-                    bevy_inspector::by_type_id::ui_for_asset(
-                        self.world,
-                        type_id,
-                        handle,
-                        ui,
-                        &type_registry,
-                    );
+                if pressed && ui.rect_contains_pointer(ui.max_rect()) {
+                    let snapshot = match *self.selection {
+                        InspectorSelection::Entities => Some(InspectorSnapshot::entities(
+                            self.world,
+                            &type_registry,
+                            self.selected_entities.as_slice(),
+                        )),
+                        InspectorSelection::Resource(type_id, _) => Some(
+                            InspectorSnapshot::resource(self.world, &type_registry, type_id),
+                        ),
+                        InspectorSelection::Asset(..) => None,
+                    };
+                    if let Some(snapshot) = snapshot {
+                        self.history.begin_inspector_edit(snapshot);
+                    }
                 }
-            },
+
+                let last_run = self.world.increment_change_tick();
+                self.inspector_ui(ui, &type_registry);
+                self.history.inspector_shown(self.world, last_run);
+            }
         }
     }
 
@@ -285,6 +518,37 @@ impl egui_dock::TabViewer for TabViewer<'_> {
     }
 }
 
+impl TabViewer<'_> {
+    fn inspector_ui(&mut self, ui: &mut egui::Ui, type_registry: &TypeRegistry) {
+        match *self.selection {
+            InspectorSelection::Entities => match self.selected_entities.as_slice() {
+                &[entity] => ui_for_entity_with_children(self.world, entity, ui),
+                entities => ui_for_entities_shared_components(self.world, entities, ui),
+            },
+            InspectorSelection::Resource(type_id, ref name) => {
+                ui.label(name);
+                bevy_inspector::by_type_id::ui_for_resource(
+                    self.world,
+                    type_id,
+                    ui,
+                    name,
+                    type_registry,
+                )
+            }
+            InspectorSelection::Asset(type_id, ref name, handle) => {
+                ui.label(name);
+                bevy_inspector::by_type_id::ui_for_asset(
+                    self.world,
+                    type_id,
+                    handle,
+                    ui,
+                    type_registry,
+                );
+            }
+        }
+    }
+}
+
 fn select_resource(
     ui: &mut egui::Ui,
     type_registry: &TypeRegistry,
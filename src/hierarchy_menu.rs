@@ -0,0 +1,218 @@
+//! Context menu of the entities in the Hierarchy tab: spawn prefabs, duplicate and despawn.
+//!
+//! Gameplay entities are spawned on the server with the same replication as the ones spawned
+//! by the game, so clients see them. Walls are part of the level, see [`crate::level_editor`].
+//! Spawning, duplicating and despawning walls, balls and spawn points can be undone.
+
+use avian2d::prelude::{Collider, Position};
+use bevy::prelude::*;
+use egui_dock::egui;
+use lightyear::prelude::{
+    server::{ClientOf, Server},
+    *,
+};
+
+use crate::{
+    edit_history::EditHistory,
+    editor::EditorCamera,
+    level::{CurrentLevel, Level, SpawnPoint, WallSpec},
+    level_editor::{DEFAULT_WALL_SIZE, level_edit},
+    protocol::{Ball, Player, Wall},
+    server::{spawn_ball, spawn_bot},
+};
+
+/// Distance between a duplicate and the original entity.
+const DUPLICATE_OFFSET: Vec2 = Vec2::new(50., -50.);
+
+/// Entities spawned from the context menu.
+#[derive(Clone, Copy)]
+enum Prefab {
+    Wall,
+    Ball,
+    Bot,
+}
+
+impl Prefab {
+    const ALL: [Prefab; 3] = [Prefab::Wall, Prefab::Ball, Prefab::Bot];
+
+    fn label(self) -> &'static str {
+        match self {
+            Prefab::Wall => "Wall",
+            Prefab::Ball => "Ball",
+            Prefab::Bot => "Player bot",
+        }
+    }
+}
+
+/// Context menu of `entity` in the Hierarchy tab.
+pub fn hierarchy_context_menu(
+    ui: &mut egui::Ui,
+    entity: Entity,
+    world: &mut World,
+    history: &mut EditHistory,
+) {
+    let server = world.query::<&Server>().iter(world).next().is_some();
+
+    ui.add_enabled_ui(server, |ui| {
+        ui.menu_button("Spawn", |ui| {
+            for prefab in Prefab::ALL {
+                if ui.button(prefab.label()).clicked() {
+                    let position = view_center(world);
+                    spawn_prefab(world, history, prefab, position);
+                    ui.close_menu();
+                }
+            }
+        });
+    })
+    .response
+    .on_disabled_hover_text("Entities are spawned on the server.");
+
+    let locked = locked_reason(world, entity);
+    // despawning the player of a client would leave it without one
+    let controlled = world.get::<ControlledBy>(entity).is_some();
+
+    let duplicate = ui
+        .add_enabled(locked.is_none(), egui::Button::new("Duplicate"))
+        .on_disabled_hover_text(locked.unwrap_or_default());
+    if duplicate.clicked() {
+        duplicate_entity(world, history, entity);
+        ui.close_menu();
+    }
+
+    let despawn = ui
+        .add_enabled(
+            locked.is_none() && !controlled,
+            egui::Button::new("Despawn"),
+        )
+        .on_disabled_hover_text(locked.unwrap_or("Players of clients can't be despawned."));
+    if despawn.clicked() {
+        if is_level_entity(world, entity) {
+            record_level_edit(world, history);
+        }
+        world.entity_mut(entity).despawn();
+        ui.close_menu();
+    }
+}
+
+/// Why `entity` can't be duplicated or despawned, if it can't.
+///
+/// Only walls, balls, spawn points, players and entities which are not networked can: the game
+/// needs its cameras, window and links, and cloning other replicated entities would copy their
+/// replication state. Walls, balls, spawn points and players are only edited on the server.
+fn locked_reason(world: &mut World, entity: Entity) -> Option<&'static str> {
+    let server = world.query::<&Server>().iter(world).next().is_some();
+    let entity = world.get_entity(entity).ok()?;
+    let prefab = entity.contains::<Wall>()
+        || entity.contains::<Ball>()
+        || entity.contains::<SpawnPoint>()
+        || entity.contains::<Player>();
+
+    if entity.contains::<Camera>() || entity.contains::<Window>() {
+        Some("The cameras and the window are needed by the game.")
+    } else if entity.contains::<Server>()
+        || entity.contains::<Client>()
+        || entity.contains::<ClientOf>()
+        || entity.contains::<Link>()
+    {
+        Some("Network links are managed by lightyear.")
+    } else if entity.contains::<Predicted>()
+        || entity.contains::<Interpolated>()
+        || entity.contains::<Confirmed>()
+    {
+        // entities replicated from the server are overwritten by it
+        Some("Replicated entities are edited on the server.")
+    } else if prefab && !server {
+        Some("Walls, balls, spawn points and players are edited on the server.")
+    } else if entity.contains::<Replicate>() && !prefab {
+        Some("Only walls, balls, spawn points and players can be replicated again.")
+    } else {
+        None
+    }
+}
+
+/// Center of the GameView tab in the world.
+fn view_center(world: &mut World) -> Vec2 {
+    world
+        .query_filtered::<&GlobalTransform, With<EditorCamera>>()
+        .single(world)
+        .map_or(Vec2::ZERO, |transform| transform.translation().truncate())
+}
+
+fn spawn_prefab(world: &mut World, history: &mut EditHistory, prefab: Prefab, position: Vec2) {
+    match prefab {
+        Prefab::Wall => {
+            record_level_edit(world, history);
+            let wall = WallSpec {
+                position,
+                size: DEFAULT_WALL_SIZE,
+                rotation: 0.,
+            };
+            world.spawn(wall.bundle());
+        }
+        Prefab::Ball => {
+            record_level_edit(world, history);
+            spawn_ball(&mut world.commands(), position);
+        }
+        Prefab::Bot => {
+            let asset_server = world.resource::<AssetServer>().clone();
+            spawn_bot(&mut world.commands(), &asset_server, position);
+        }
+    }
+    world.flush();
+}
+
+/// Spawn a copy of `entity` next to it.
+///
+/// Gameplay entities are spawned again from their prefab, to be replicated like the original.
+/// Other entities, which are not networked, are cloned without their children.
+fn duplicate_entity(world: &mut World, history: &mut EditHistory, entity: Entity) {
+    if locked_reason(world, entity).is_some() {
+        return;
+    }
+    let Ok(entity_ref) = world.get_entity(entity) else {
+        return;
+    };
+    let position = entity_ref
+        .get::<Position>()
+        .map(|position| position.0)
+        .or_else(|| {
+            entity_ref
+                .get::<GlobalTransform>()
+                .map(|transform| transform.translation().truncate())
+        })
+        .unwrap_or_default()
+        + DUPLICATE_OFFSET;
+
+    if entity_ref.contains::<Wall>() {
+        let wall = entity_ref
+            .get::<GlobalTransform>()
+            .zip(entity_ref.get::<Collider>())
+            .and_then(|(transform, collider)| WallSpec::from_wall(transform, collider));
+        if let Some(wall) = wall {
+            record_level_edit(world, history);
+            world.spawn(WallSpec { position, ..wall }.bundle());
+        }
+    } else if entity_ref.contains::<SpawnPoint>() {
+        record_level_edit(world, history);
+        world.spawn((SpawnPoint, Transform::from_translation(position.extend(0.))));
+    } else if entity_ref.contains::<Ball>() {
+        spawn_prefab(world, history, Prefab::Ball, position);
+    } else if entity_ref.contains::<Player>() {
+        spawn_prefab(world, history, Prefab::Bot, position);
+    } else {
+        world.entity_mut(entity).clone_and_spawn();
+    }
+}
+
+/// Walls, balls and spawn points, which are saved in the level.
+fn is_level_entity(world: &World, entity: Entity) -> bool {
+    world.get_entity(entity).is_ok_and(|entity| {
+        entity.contains::<Wall>() || entity.contains::<Ball>() || entity.contains::<SpawnPoint>()
+    }) && world.contains_resource::<CurrentLevel>()
+}
+
+/// Remember the level before changing its entities, to undo the change.
+fn record_level_edit(world: &mut World, history: &mut EditHistory) {
+    let level = Level::from_world(world);
+    history.record(level_edit(world.resource::<CurrentLevel>(), level));
+}
@@ -0,0 +1,73 @@
+//! Host mode: the server runs headless in a thread of the client's process, to play without
+//! starting a separate server.
+//!
+//! With the in-memory transport the client is linked to the server through crossbeam channels,
+//! and nobody else can join. With UDP the server also listens on `SERVER_ADDR` for other clients.
+
+use std::{
+    net::{IpAddr, Ipv4Addr, SocketAddr},
+    thread,
+};
+
+use avian2d::prelude::{Gravity, PhysicsPlugins};
+use bevy::{app::ScheduleRunnerPlugin, input::InputPlugin, prelude::*, state::app::StatesPlugin};
+use lightyear::prelude::server::ServerPlugins;
+
+use crate::{
+    cvars::Cvars,
+    protocol::ProtocolPlugin,
+    server::MyServerPlugin,
+    shared::SharedPlugin,
+    transport::{MemoryClientLink, MemoryServerLinks, Transport, memory_link},
+};
+
+/// Address identifying the client of the host on its in-memory link.
+const HOST_CLIENT_ADDR: SocketAddr = SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), 4000);
+
+/// Start the server in another thread, returns the link of the client for the in-memory
+/// transport.
+pub fn start_server(cvars: Cvars, transport: Transport) -> Option<MemoryClientLink> {
+    let (server_link, client_link) = match transport {
+        Transport::Memory => {
+            let (server_link, client_link) = memory_link(HOST_CLIENT_ADDR);
+            (Some(server_link), Some(client_link))
+        }
+        _ => (None, None),
+    };
+
+    // apps can't be sent to another thread, the server is built in its own
+    thread::spawn(move || {
+        let tick_duration = cvars.tick_duration();
+
+        let mut app = App::new();
+        app.add_plugins((
+            MinimalPlugins.set(ScheduleRunnerPlugin::run_loop(tick_duration)),
+            StatesPlugin,
+            InputPlugin,
+            AssetPlugin::default(),
+        ))
+        .init_asset::<Image>()
+        .insert_resource(cvars)
+        .insert_resource(transport)
+        .add_plugins((
+            ServerPlugins { tick_duration },
+            PhysicsPlugins::default()
+                .build()
+                // disable Sync as it is handled by lightyear_avian
+                .disable::<avian2d::sync::SyncPlugin>(),
+        ))
+        .insert_resource(Gravity(Vec2::ZERO))
+        .add_plugins((SharedPlugin, ProtocolPlugin, MyServerPlugin));
+
+        if let Some(server_link) = server_link {
+            app.world_mut()
+                .resource_mut::<MemoryServerLinks>()
+                .0
+                .push(server_link);
+        }
+
+        app.run()
+    });
+
+    client_link
+}
@@ -0,0 +1,361 @@
+//! Levels are the walls, balls and spawn points of the arena, saved as RON files in [`LEVELS_DIR`].
+//!
+//! Walls are not replicated: the server loads the level file and sends its content to clients
+//! with [`ChangeLevel`], then both spawn the walls of the [`CurrentLevel`].
+//! Balls are spawned by the server and replicated, spawn points only matter to the server.
+
+use std::{fs, path::PathBuf};
+
+use avian2d::prelude::{Collider, Position, Rotation};
+use bevy::{
+    ecs::system::{SystemParam, SystemState},
+    prelude::*,
+};
+use lightyear::prelude::{
+    server::{Server, ServerMultiMessageSender},
+    *,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    admission::Admitted,
+    protocol::{Ball, ChangeLevel, ControlChannel, Wall},
+    server::spawn_ball,
+};
+
+/// Directory of the level files, `<name>.ron`.
+pub const LEVELS_DIR: &str = "assets/levels";
+
+pub const DEFAULT_LEVEL: &str = "arena";
+
+pub struct LevelPlugin;
+
+impl Plugin for LevelPlugin {
+    fn build(&self, app: &mut App) {
+        app.register_type::<SpawnPoint>()
+            .init_resource::<CurrentLevel>()
+            .add_systems(Update, spawn_level.run_if(resource_changed::<CurrentLevel>));
+    }
+}
+
+pub struct ServerLevelPlugin;
+
+impl Plugin for ServerLevelPlugin {
+    fn build(&self, app: &mut App) {
+        let current_level = CurrentLevel::load(DEFAULT_LEVEL).unwrap_or_else(|e| {
+            error!("Failed to load the default level: {e}");
+            CurrentLevel::default()
+        });
+
+        app.insert_resource(current_level)
+            .add_observer(send_level_to_new_client)
+            .add_systems(
+                Update,
+                (spawn_level_balls, broadcast_level_change)
+                    .run_if(resource_changed::<CurrentLevel>),
+            );
+    }
+}
+
+pub struct ClientLevelPlugin;
+
+impl Plugin for ClientLevelPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Update, receive_level_change);
+    }
+}
+
+/// Content of a level file.
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq)]
+#[serde(default)]
+pub struct Level {
+    pub walls: Vec<WallSpec>,
+    /// Initial positions of the balls.
+    pub balls: Vec<Vec2>,
+    /// Positions where players spawn, used in turn.
+    pub spawn_points: Vec<Vec2>,
+}
+
+/// Position, size and angle of a wall.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct WallSpec {
+    pub position: Vec2,
+    pub size: Vec2,
+    /// In radians.
+    #[serde(default)]
+    pub rotation: f32,
+}
+
+impl WallSpec {
+    /// The spec of a spawned wall, if it is rectangular.
+    pub fn from_wall(transform: &GlobalTransform, collider: &Collider) -> Option<Self> {
+        let half_size = collider.shape_scaled().as_cuboid()?.half_extents;
+        Some(WallSpec {
+            position: transform.translation().truncate(),
+            size: Vec2::new(half_size.x, half_size.y) * 2.,
+            rotation: transform.rotation().to_euler(EulerRot::XYZ).2,
+        })
+    }
+
+    pub fn bundle(&self) -> impl Bundle {
+        (
+            Wall,
+            Collider::rectangle(self.size.x, self.size.y),
+            Transform::from_translation(self.position.extend(0.))
+                .with_rotation(Quat::from_rotation_z(self.rotation)),
+        )
+    }
+}
+
+/// Position where a player can spawn.
+#[derive(Component, Reflect, Debug, Default)]
+#[reflect(Component)]
+#[require(Name::new("Spawn point"), Transform)]
+pub struct SpawnPoint;
+
+impl Level {
+    pub fn path(name: &str) -> PathBuf {
+        PathBuf::from(LEVELS_DIR).join(format!("{name}.ron"))
+    }
+
+    pub fn load(name: &str) -> Result<Self, String> {
+        if !is_valid_level_name(name) {
+            return Err(format!("invalid level name {name:?}"));
+        }
+        let path = Self::path(name);
+        let contents =
+            fs::read_to_string(&path).map_err(|e| format!("failed to read {path:?}: {e}"))?;
+        ron::from_str(&contents).map_err(|e| format!("failed to parse {path:?}: {e}"))
+    }
+
+    pub fn save(&self, name: &str) -> Result<(), String> {
+        if !is_valid_level_name(name) {
+            return Err(format!("invalid level name {name:?}"));
+        }
+        let contents = ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default())
+            .map_err(|e| format!("failed to serialize the level: {e}"))?;
+
+        let path = Self::path(name);
+        fs::create_dir_all(LEVELS_DIR)
+            .and_then(|()| fs::write(&path, contents))
+            .map_err(|e| format!("failed to write {path:?}: {e}"))
+    }
+
+    /// The walls, balls and spawn points currently in `world`.
+    pub fn from_world(world: &mut World) -> Self {
+        let mut state = SystemState::<LevelEntities>::new(world);
+        state.get(world).level()
+    }
+}
+
+/// Level names are file names, so only allow letters, digits, `-` and `_`.
+pub fn is_valid_level_name(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+pub fn level_exists(name: &str) -> bool {
+    is_valid_level_name(name) && Level::path(name).is_file()
+}
+
+/// Names of the levels in [`LEVELS_DIR`], sorted.
+pub fn level_names() -> Vec<String> {
+    let Ok(entries) = fs::read_dir(LEVELS_DIR) else {
+        return Vec::new();
+    };
+    let mut names: Vec<String> = entries
+        .filter_map(|entry| {
+            let path = entry.ok()?.path();
+            if path.extension()? != "ron" {
+                return None;
+            }
+            Some(path.file_stem()?.to_str()?.to_string())
+        })
+        .collect();
+    names.sort();
+    names
+}
+
+/// The level being played, and its name.
+#[derive(Resource, Debug, Clone, PartialEq)]
+pub struct CurrentLevel {
+    pub name: String,
+    pub level: Level,
+}
+
+impl Default for CurrentLevel {
+    fn default() -> Self {
+        Self {
+            name: DEFAULT_LEVEL.to_string(),
+            level: Level::default(),
+        }
+    }
+}
+
+impl CurrentLevel {
+    pub fn load(name: &str) -> Result<Self, String> {
+        Ok(Self {
+            name: name.to_string(),
+            level: Level::load(name)?,
+        })
+    }
+}
+
+/// Entities which make up a level.
+#[derive(SystemParam)]
+pub struct LevelEntities<'w, 's> {
+    wall_q: Query<'w, 's, (Entity, &'static GlobalTransform, &'static Collider), With<Wall>>,
+    ball_q: Query<'w, 's, (Entity, &'static Position, &'static Collider), With<Ball>>,
+    spawn_point_q: Query<'w, 's, (Entity, &'static GlobalTransform), With<SpawnPoint>>,
+}
+
+impl LevelEntities<'_, '_> {
+    pub fn level(&self) -> Level {
+        Level {
+            walls: self
+                .wall_q
+                .iter()
+                .filter_map(|(_, transform, collider)| WallSpec::from_wall(transform, collider))
+                .collect(),
+            balls: self
+                .ball_q
+                .iter()
+                .map(|(_, position, _)| position.0)
+                .collect(),
+            spawn_points: self
+                .spawn_point_q
+                .iter()
+                .map(|(_, transform)| transform.translation().truncate())
+                .collect(),
+        }
+    }
+
+    /// The wall or spawn point at `point`.
+    pub fn static_entity_at(&self, point: Vec2, spawn_point_radius: f32) -> Option<Entity> {
+        let spawn_point = self
+            .spawn_point_q
+            .iter()
+            .find(|(_, transform)| {
+                transform.translation().truncate().distance(point) <= spawn_point_radius
+            })
+            .map(|(entity, _)| entity);
+
+        spawn_point.or_else(|| {
+            self.wall_q
+                .iter()
+                .find(|(_, transform, collider)| {
+                    let (_, rotation, translation) = transform.to_scale_rotation_translation();
+                    collider.contains_point(
+                        Position(translation.truncate()),
+                        Rotation::radians(rotation.to_euler(EulerRot::XYZ).2),
+                        point,
+                    )
+                })
+                .map(|(entity, ..)| entity)
+        })
+    }
+
+    /// The ball at `point`.
+    pub fn ball_at(&self, point: Vec2) -> Option<Entity> {
+        self.ball_q
+            .iter()
+            .find(|(_, position, collider)| {
+                collider.contains_point(**position, Rotation::IDENTITY, point)
+            })
+            .map(|(entity, ..)| entity)
+    }
+}
+
+/// Replace the walls and spawn points with the ones of the new level.
+fn spawn_level(
+    mut commands: Commands,
+    current_level: Res<CurrentLevel>,
+    level_q: Query<Entity, Or<(With<Wall>, With<SpawnPoint>)>>,
+) {
+    for entity in level_q.iter() {
+        commands.entity(entity).despawn();
+    }
+
+    let level = &current_level.level;
+    for wall in &level.walls {
+        commands.spawn(wall.bundle());
+    }
+    for position in &level.spawn_points {
+        commands.spawn((SpawnPoint, Transform::from_translation(position.extend(0.))));
+    }
+
+    info!("Loaded level {:?}", current_level.name);
+}
+
+/// Replace the balls with the ones of the new level.
+fn spawn_level_balls(
+    mut commands: Commands,
+    current_level: Res<CurrentLevel>,
+    ball_q: Query<Entity, With<Ball>>,
+) {
+    for ball in ball_q.iter() {
+        commands.entity(ball).despawn();
+    }
+    for position in &current_level.level.balls {
+        spawn_ball(&mut commands, *position);
+    }
+}
+
+fn change_level_message(current_level: &CurrentLevel) -> ChangeLevel {
+    ChangeLevel {
+        name: current_level.name.clone(),
+        level: current_level.level.clone(),
+    }
+}
+
+fn send_level_to_new_client(
+    trigger: Trigger<OnAdd, Admitted>,
+    current_level: Res<CurrentLevel>,
+    mut sender_q: Query<&mut MessageSender<ChangeLevel>>,
+) {
+    if let Ok(mut sender) = sender_q.get_mut(trigger.target()) {
+        sender.send::<ControlChannel>(change_level_message(&current_level));
+    }
+}
+
+fn broadcast_level_change(
+    current_level: Res<CurrentLevel>,
+    // one server per transport
+    server_q: Query<&Server>,
+    mut sender: ServerMultiMessageSender,
+) {
+    // clients load the current level when they are admitted
+    if current_level.is_added() {
+        return;
+    }
+
+    send_level_to_clients(&current_level, &server_q, &mut sender);
+}
+
+/// Send the current level to every client of every server.
+pub(crate) fn send_level_to_clients(
+    current_level: &CurrentLevel,
+    server_q: &Query<&Server>,
+    sender: &mut ServerMultiMessageSender,
+) {
+    let message = change_level_message(current_level);
+    for server in server_q.iter() {
+        if let Err(e) = sender.send::<_, ControlChannel>(&message, server, &NetworkTarget::All) {
+            error!("Failed to broadcast level change: {e:?}");
+        }
+    }
+}
+
+fn receive_level_change(
+    mut receiver: Single<&mut MessageReceiver<ChangeLevel>, With<Client>>,
+    mut current_level: ResMut<CurrentLevel>,
+) {
+    for change in receiver.receive() {
+        current_level.set_if_neq(CurrentLevel {
+            name: change.name,
+            level: change.level,
+        });
+    }
+}
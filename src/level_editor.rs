@@ -0,0 +1,376 @@
+//! Level tab of the editor: place and remove walls, balls and spawn points in the GameView tab,
+//! resize walls and save the level to its file.
+//!
+//! Levels are edited on the server, where balls are spawned. Every change of the walls and spawn
+//! points, from any tab, is sent to clients as it is made, so that they predict with the same
+//! walls as the server simulates, and is kept in the [`CurrentLevel`] for clients joining later.
+//! Saving only writes the level to its file. Undoing an edit reloads the level as it was before.
+
+use avian2d::prelude::Collider;
+use bevy::{prelude::*, transform::TransformSystem, window::PrimaryWindow};
+use bevy_inspector_egui::bevy_egui::EguiContext;
+use egui_dock::egui;
+use lightyear::prelude::server::{Server, ServerMultiMessageSender};
+
+use crate::{
+    edit_history::{Change, Edit, EditHistory},
+    editor::{EditorCamera, UiState, cursor_world_position},
+    level::{CurrentLevel, Level, LevelEntities, SpawnPoint, WallSpec, send_level_to_clients},
+    protocol::Wall,
+    server::spawn_ball,
+    transform_gizmo::{TransformGizmo, drag_transform_gizmo},
+};
+
+/// Size of the walls placed with the wall tool.
+pub(crate) const DEFAULT_WALL_SIZE: Vec2 = Vec2::new(40., 40.);
+
+/// Radius of the circle drawn at spawn points, in which they can be clicked.
+const SPAWN_POINT_RADIUS: f32 = 16.;
+
+const SPAWN_POINT_COLOR: Color = Color::srgb(0.2, 0.9, 0.9);
+
+pub struct LevelEditorPlugin;
+
+impl Plugin for LevelEditorPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<LevelEditor>()
+            .add_systems(
+                Update,
+                (
+                    follow_current_level.run_if(resource_changed::<CurrentLevel>),
+                    (
+                        use_level_tool
+                            .after(drag_transform_gizmo)
+                            .run_if(any_with_component::<Server>),
+                        draw_spawn_points,
+                    )
+                        .run_if(resource_exists::<UiState>),
+                ),
+            )
+            // walls are sent with their `GlobalTransform`, once the edits of this frame are
+            // propagated to it
+            .add_systems(
+                PostUpdate,
+                send_level_edits
+                    .after(TransformSystem::TransformPropagate)
+                    .run_if(any_with_component::<Server>),
+            );
+    }
+}
+
+/// What clicking in the GameView tab does.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum LevelTool {
+    /// Select walls and spawn points, which can't be picked like sprites.
+    #[default]
+    Select,
+    Wall,
+    Ball,
+    SpawnPoint,
+    Remove,
+}
+
+impl LevelTool {
+    const ALL: [LevelTool; 5] = [
+        LevelTool::Select,
+        LevelTool::Wall,
+        LevelTool::Ball,
+        LevelTool::SpawnPoint,
+        LevelTool::Remove,
+    ];
+
+    fn label(self) -> &'static str {
+        match self {
+            LevelTool::Select => "Select",
+            LevelTool::Wall => "Wall",
+            LevelTool::Ball => "Ball",
+            LevelTool::SpawnPoint => "Spawn point",
+            LevelTool::Remove => "Remove",
+        }
+    }
+}
+
+#[derive(Resource, Default)]
+pub struct LevelEditor {
+    pub tool: LevelTool,
+    /// Name under which the level is saved, the current level by default.
+    name: String,
+    /// Name of the current level, to notice when another one is loaded.
+    level_name: String,
+    /// Result of the last save.
+    status: Option<Result<String, String>>,
+    /// Level before the size of the selected wall started being dragged.
+    resize_start: Option<Level>,
+}
+
+/// Content of the Level tab.
+pub fn level_editor_ui(
+    ui: &mut egui::Ui,
+    world: &mut World,
+    selected: Option<Entity>,
+    history: &mut EditHistory,
+) {
+    if world.query::<&Server>().iter(world).next().is_none() {
+        ui.label("Levels are edited on the server.");
+        return;
+    }
+
+    let current_name = world.resource::<CurrentLevel>().name.clone();
+    let mut save = false;
+    let mut undo = false;
+    let mut redo = false;
+
+    {
+        let mut editor = world.resource_mut::<LevelEditor>();
+        ui.label(format!("Current level: {current_name}"));
+        ui.horizontal(|ui| {
+            ui.text_edit_singleline(&mut editor.name);
+            save = ui.button("Save").clicked();
+        });
+        match &editor.status {
+            Some(Ok(message)) => {
+                ui.label(message);
+            }
+            Some(Err(error)) => {
+                ui.colored_label(egui::Color32::RED, error);
+            }
+            None => {}
+        }
+
+        ui.separator();
+        ui.horizontal_wrapped(|ui| {
+            for tool in LevelTool::ALL {
+                ui.selectable_value(&mut editor.tool, tool, tool.label());
+            }
+        });
+        ui.horizontal(|ui| {
+            undo = ui
+                .add_enabled(history.can_undo(), egui::Button::new("Undo"))
+                .clicked();
+            redo = ui
+                .add_enabled(history.can_redo(), egui::Button::new("Redo"))
+                .clicked();
+        });
+    }
+
+    if let Some(wall) = selected.filter(|entity| world.get::<Wall>(*entity).is_some()) {
+        ui.separator();
+        wall_size_ui(ui, world, wall, history);
+    }
+
+    if save {
+        let status = save_level(world);
+        world.resource_mut::<LevelEditor>().status = Some(status);
+    }
+    if undo {
+        history.undo(world);
+    } else if redo {
+        history.redo(world);
+    }
+}
+
+/// Width and height of the selected wall.
+fn wall_size_ui(ui: &mut egui::Ui, world: &mut World, wall: Entity, history: &mut EditHistory) {
+    let Some((collider, transform)) = world
+        .get::<Collider>(wall)
+        .zip(world.get::<Transform>(wall))
+    else {
+        return;
+    };
+    let Some(cuboid) = collider.shape_scaled().as_cuboid() else {
+        ui.label("Only rectangular walls can be resized.");
+        return;
+    };
+    let scale = transform.scale.truncate();
+    let size = Vec2::new(cuboid.half_extents.x, cuboid.half_extents.y) * 2.;
+
+    let mut new_size = size;
+    let response = ui
+        .horizontal(|ui| {
+            ui.label("Wall size");
+            ui.add(egui::DragValue::new(&mut new_size.x).range(1.0..=10_000.0))
+                | ui.add(egui::DragValue::new(&mut new_size.y).range(1.0..=10_000.0))
+        })
+        .inner;
+
+    // one edit per drag, recorded when it stops, or per typed value
+    if response.drag_started() {
+        let level = Level::from_world(world);
+        world.resource_mut::<LevelEditor>().resize_start = Some(level);
+    }
+    if new_size != size {
+        if !response.dragged() {
+            let level = Level::from_world(world);
+            history.record(level_edit(world.resource::<CurrentLevel>(), level));
+        }
+        // the collider is scaled by the transform
+        let unscaled = new_size / scale;
+        world
+            .entity_mut(wall)
+            .insert(Collider::rectangle(unscaled.x, unscaled.y));
+    }
+    if response.drag_stopped() {
+        let start = world.resource_mut::<LevelEditor>().resize_start.take();
+        let current = Level::from_world(world);
+        if let Some(level) = start.filter(|level| *level != current) {
+            history.record(level_edit(world.resource::<CurrentLevel>(), level));
+        }
+    }
+}
+
+/// Save the level under the name chosen in the Level tab.
+fn save_level(world: &mut World) -> Result<String, String> {
+    let name = world.resource::<LevelEditor>().name.clone();
+    let level = Level::from_world(world);
+    level.save(&name)?;
+
+    *world.resource_mut::<CurrentLevel>() = CurrentLevel {
+        name: name.clone(),
+        level,
+    };
+    Ok(format!("Saved {:?}", Level::path(&name)))
+}
+
+/// Follow the current level, to save it under its own name by default.
+fn follow_current_level(current_level: Res<CurrentLevel>, mut editor: ResMut<LevelEditor>) {
+    if editor.level_name != current_level.name {
+        editor.level_name = current_level.name.clone();
+        editor.name = current_level.name.clone();
+    }
+}
+
+/// Edit which restores `level`, as it was before an edit.
+pub(crate) fn level_edit(current_level: &CurrentLevel, level: Level) -> Edit {
+    Edit(vec![Change::Level {
+        name: current_level.name.clone(),
+        level,
+    }])
+}
+
+/// Apply the selected tool where the GameView tab is clicked.
+#[allow(clippy::too_many_arguments)]
+fn use_level_tool(
+    mut commands: Commands,
+    editor: Res<LevelEditor>,
+    current_level: Res<CurrentLevel>,
+    mut ui_state: ResMut<UiState>,
+    gizmo: Res<TransformGizmo>,
+    mouse: Res<ButtonInput<MouseButton>>,
+    keyboard: Res<ButtonInput<KeyCode>>,
+    window: Single<&Window, With<PrimaryWindow>>,
+    mut egui_context: Single<&mut EguiContext, With<PrimaryWindow>>,
+    camera: Single<(&Camera, &GlobalTransform), With<EditorCamera>>,
+    level_entities: LevelEntities,
+) {
+    if !mouse.just_pressed(MouseButton::Left)
+        || gizmo.is_dragging()
+        || egui_context.get_mut().wants_pointer_input()
+    {
+        return;
+    }
+    let (camera, camera_transform) = *camera;
+    let Some(cursor) = cursor_world_position(&window, camera, camera_transform) else {
+        return;
+    };
+
+    match editor.tool {
+        LevelTool::Select => {
+            if let Some(entity) = level_entities.static_entity_at(cursor, SPAWN_POINT_RADIUS) {
+                let add = keyboard.any_pressed([
+                    KeyCode::ControlLeft,
+                    KeyCode::ControlRight,
+                    KeyCode::ShiftLeft,
+                    KeyCode::ShiftRight,
+                ]);
+                ui_state.select(entity, add);
+            }
+        }
+        LevelTool::Wall => {
+            ui_state
+                .history_mut()
+                .record(level_edit(&current_level, level_entities.level()));
+            let wall = WallSpec {
+                position: cursor,
+                size: DEFAULT_WALL_SIZE,
+                rotation: 0.,
+            };
+            commands.spawn(wall.bundle());
+        }
+        LevelTool::Ball => {
+            ui_state
+                .history_mut()
+                .record(level_edit(&current_level, level_entities.level()));
+            spawn_ball(&mut commands, cursor);
+        }
+        LevelTool::SpawnPoint => {
+            ui_state
+                .history_mut()
+                .record(level_edit(&current_level, level_entities.level()));
+            commands.spawn((SpawnPoint, Transform::from_translation(cursor.extend(0.))));
+        }
+        LevelTool::Remove => {
+            let target = level_entities
+                .ball_at(cursor)
+                .or_else(|| level_entities.static_entity_at(cursor, SPAWN_POINT_RADIUS));
+            if let Some(entity) = target {
+                ui_state
+                    .history_mut()
+                    .record(level_edit(&current_level, level_entities.level()));
+                commands.entity(entity).despawn();
+            }
+        }
+    }
+}
+
+/// Spawn points have no sprite, so show them while the editor is open.
+fn draw_spawn_points(spawn_point_q: Query<&GlobalTransform, With<SpawnPoint>>, mut gizmos: Gizmos) {
+    for transform in spawn_point_q.iter() {
+        let position = transform.translation().truncate();
+        gizmos.circle_2d(
+            Isometry2d::from_translation(position),
+            SPAWN_POINT_RADIUS,
+            SPAWN_POINT_COLOR,
+        );
+        gizmos.cross_2d(
+            Isometry2d::from_translation(position),
+            SPAWN_POINT_RADIUS,
+            SPAWN_POINT_COLOR,
+        );
+    }
+}
+
+/// Send the walls and spawn points to clients when they are edited.
+fn send_level_edits(
+    mut current_level: ResMut<CurrentLevel>,
+    edited_q: Query<
+        (),
+        (
+            Or<(With<Wall>, With<SpawnPoint>)>,
+            Or<(Changed<Transform>, Changed<Collider>)>,
+        ),
+    >,
+    mut removed_walls: RemovedComponents<Wall>,
+    mut removed_spawn_points: RemovedComponents<SpawnPoint>,
+    level_entities: LevelEntities,
+    server_q: Query<&Server>,
+    mut sender: ServerMultiMessageSender,
+) {
+    let removed = removed_walls.read().count() + removed_spawn_points.read().count() > 0;
+    if edited_q.is_empty() && !removed {
+        return;
+    }
+
+    // also true when the level was just spawned from the current level
+    let edited = level_entities.level();
+    if edited.walls == current_level.level.walls
+        && edited.spawn_points == current_level.level.spawn_points
+    {
+        return;
+    }
+
+    // balls are replicated, and respawning the level would reset them
+    let current_level = current_level.bypass_change_detection();
+    current_level.level.walls = edited.walls;
+    current_level.level.spawn_points = edited.spawn_points;
+    send_level_to_clients(current_level, &server_q, &mut sender);
+}
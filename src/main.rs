@@ -1,30 +1,64 @@
+mod admin;
+mod admission;
+mod chat;
 mod client;
+mod config;
+mod cvars;
+#[cfg(not(target_family = "wasm"))]
+mod discovery;
+mod edit_history;
 mod editor;
+mod hierarchy_menu;
+#[cfg(not(target_family = "wasm"))]
+mod host;
+mod level;
+mod level_editor;
+mod net_stats;
+mod network_inspector;
+mod prediction_gizmos;
 mod protocol;
+mod rollback_diagnostics;
+mod scoreboard;
 mod server;
 mod shared;
+#[cfg(test)]
+mod tests;
+mod transform_gizmo;
+mod transport;
 
-use std::time::Duration;
+use std::{net::SocketAddr, time::Duration};
 
 use avian2d::{PhysicsPlugins, prelude::PhysicsDebugPlugin};
 use bevy::prelude::*;
-use clap::{Parser, Subcommand};
+use clap::{Args, Parser, Subcommand};
 use lightyear::prelude::{client::ClientPlugins, server::ServerPlugins};
 
+#[cfg(not(target_family = "wasm"))]
+use crate::discovery::{ServerBrowser, ServerBrowserPlugin, ServerDiscoveryPlugin, ServerInfo};
 use crate::{
+    admin::{RconPassword, StdinConsolePlugin},
     client::MyClientPlugin,
-    editor::EditorPlugin,
+    cvars::{Cvars, parse_cvar_arg},
+    editor::{EditorCamera, EditorPlugin},
     protocol::{CliClientOptions, ProtocolPlugin},
     server::MyServerPlugin,
-    shared::{FIXED_TIMESTEP_HZ, SharedPlugin},
+    shared::{LinkConditions, SERVER_ADDR, SharedPlugin},
+    transport::{
+        ServerTransportOptions, Transport, WEBSOCKET_SERVER_ADDR, WEBTRANSPORT_SERVER_ADDR,
+    },
 };
 
+const WINDOW_RESOLUTION: (f32, f32) = (640., 480.);
+
 /// CLI options to create an [`App`]
 #[derive(Parser, Debug)]
 #[command(version, about)]
 pub struct Cli {
     #[command(subcommand)]
     pub mode: Mode,
+    /// Set a cvar, e.g. `--cvar player_speed=200`. Can be repeated.
+    #[arg(long = "cvar", global = true, value_parser = parse_cvar_arg)]
+    pub cvars: Vec<(String, String)>,
 }
 
 #[derive(Subcommand, Debug)]
@@ -34,50 +68,225 @@ pub enum Mode {
         id: u64,
         #[arg(short, long, default_value_t = 4000)]
         port: u16,
+        /// Display name shown to other players.
+        #[arg(short, long, default_value = "Player")]
+        name: String,
+        #[arg(long, value_enum, default_value_t)]
+        transport: Transport,
+        /// Address of the server. Defaults to the local server address of the transport.
+        #[arg(long)]
+        server: Option<SocketAddr>,
+        /// Digest of the server certificate, printed by the server, for WebTransport.
+        #[arg(long, default_value = "")]
+        certificate_digest: String,
+        /// List the servers found on the local network instead of connecting to `--server`.
+        #[arg(long)]
+        browse: bool,
+        #[command(flatten)]
+        simulation: SimulationArgs,
+    },
+    /// Plays with a server running in the same process.
+    #[cfg(not(target_family = "wasm"))]
+    Host {
+        /// Display name shown to other players.
+        #[arg(short, long, default_value = "Player")]
+        name: String,
+        /// `memory` to play alone, `udp` to let other clients join.
+        #[arg(long, value_enum, default_value = "memory")]
+        transport: Transport,
+        #[command(flatten)]
+        simulation: SimulationArgs,
     },
-    Server,
+    /// Listens on UDP, and optionally on WebTransport and WebSocket for browsers.
+    Server {
+        /// Also accept WebTransport clients.
+        #[arg(long)]
+        webtransport: bool,
+        /// Also accept WebSocket clients.
+        #[arg(long)]
+        websocket: bool,
+        /// Password of remote admin commands, which are disabled without it.
+        #[arg(long)]
+        rcon_password: Option<String>,
+        /// Name advertised to clients on the local network.
+        #[arg(long, default_value = "Lightyear server")]
+        server_name: String,
+        #[command(flatten)]
+        simulation: SimulationArgs,
+    },
+}
+
+/// Simulated network conditions applied to received packets, for local testing.
+#[derive(Args, Debug)]
+pub struct SimulationArgs {
+    /// Added latency, in milliseconds.
+    #[arg(long, default_value_t = 0)]
+    sim_latency: u64,
+    /// Random variation of the latency, in milliseconds.
+    #[arg(long, default_value_t = 0)]
+    sim_jitter: u64,
+    /// Ratio of dropped packets, between 0 and 1.
+    #[arg(long, default_value_t = 0.)]
+    sim_loss: f32,
+}
+
+impl From<SimulationArgs> for LinkConditions {
+    fn from(args: SimulationArgs) -> Self {
+        Self {
+            latency_ms: args.sim_latency,
+            jitter_ms: args.sim_jitter,
+            loss: args.sim_loss.clamp(0., 1.),
+        }
+    }
+}
+
+/// Command line arguments.
+///
+/// In the browser there is no command line, so they are read from the URL query instead:
+/// `?client&transport=websocket&name=Bob` is parsed as `client --transport websocket --name Bob`.
+fn cli_args() -> Vec<String> {
+    #[cfg(not(target_family = "wasm"))]
+    {
+        std::env::args().collect()
+    }
+
+    #[cfg(target_family = "wasm")]
+    {
+        let query = web_sys::window()
+            .and_then(|window| window.location().search().ok())
+            .unwrap_or_default();
+
+        let mut args = vec![String::from(env!("CARGO_PKG_NAME"))];
+        for param in query.trim_start_matches('?').split('&') {
+            match param.split_once('=') {
+                Some((key, value)) => {
+                    args.push(format!("--{key}"));
+                    args.push(
+                        percent_encoding::percent_decode_str(value)
+                            .decode_utf8_lossy()
+                            .into_owned(),
+                    );
+                }
+                None if !param.is_empty() => args.push(format!("--{param}")),
+                None => {}
+            }
+        }
+        // the first parameter is the mode
+        if let Some(mode) = args.get_mut(1) {
+            *mode = mode.trim_start_matches('-').to_string();
+        }
+        args
+    }
 }
 
 fn main() {
-    let cli = Cli::parse();
+    let cli = Cli::parse_from(cli_args());
     let mut app = App::new();
-    let resolution = (640., 480.).into();
+    // read before the plugins, which need the tick rate
+    let cvars = Cvars::load(&cli.cvars);
+    let tick_duration = cvars.tick_duration();
+    app.insert_resource(cvars.clone());
 
     match cli.mode {
-        Mode::Client { id, port: _ } => {
-            app.add_plugins((
-                DefaultPlugins.set(WindowPlugin {
-                    primary_window: Some(Window {
-                        title: String::from("Client"),
-                        resolution,
-                        ..default()
-                    }),
-                    ..default()
-                }),
-                ClientPlugins {
-                    tick_duration: Duration::from_secs_f64(1.0 / FIXED_TIMESTEP_HZ),
-                },
-                MyClientPlugin,
-            ));
-
-            app.world_mut().spawn(CliClientOptions { id });
+        Mode::Client {
+            id,
+            port: _,
+            name,
+            transport,
+            server,
+            certificate_digest,
+            browse,
+            simulation,
+        } => {
+            add_client(&mut app, "Client", tick_duration, transport, simulation);
+            let server_addr = server.unwrap_or(match transport {
+                Transport::WebSocket => WEBSOCKET_SERVER_ADDR,
+                Transport::WebTransport => WEBTRANSPORT_SERVER_ADDR,
+                _ => SERVER_ADDR,
+            });
+            let options = CliClientOptions {
+                id,
+                name,
+                server_addr,
+                certificate_digest,
+            };
+            if browse {
+                #[cfg(not(target_family = "wasm"))]
+                app.add_plugins(ServerBrowserPlugin)
+                    .insert_resource(ServerBrowser { options });
+                // servers are discovered with UDP broadcasts, which browsers can't receive
+                #[cfg(target_family = "wasm")]
+                {
+                    warn!("Servers can't be discovered in the browser, joining {server_addr}");
+                    app.world_mut().spawn(options);
+                }
+            } else {
+                app.world_mut().spawn(options);
+            }
+        }
+        #[cfg(not(target_family = "wasm"))]
+        Mode::Host {
+            name,
+            transport,
+            simulation,
+        } => {
+            add_client(&mut app, "Host", tick_duration, transport, simulation);
+            let transport = match transport {
+                Transport::Udp | Transport::Memory => transport,
+                _ => {
+                    warn!("The host only serves the udp and memory transports, using memory");
+                    app.insert_resource(Transport::Memory);
+                    Transport::Memory
+                }
+            };
+            if let Some(client_link) = host::start_server(cvars, transport) {
+                app.insert_resource(client_link);
+            }
+            app.world_mut().spawn(CliClientOptions {
+                id: 0,
+                name,
+                server_addr: SERVER_ADDR,
+                certificate_digest: String::new(),
+            });
         }
-        Mode::Server => {
+        Mode::Server {
+            webtransport,
+            websocket,
+            server_name,
+            rcon_password,
+            simulation,
+        } => {
             // TODO: just minimal plugins?
             app.add_plugins((
                 DefaultPlugins.set(WindowPlugin {
                     primary_window: Some(Window {
                         title: String::from("Server"),
-                        resolution,
+                        resolution: WINDOW_RESOLUTION.into(),
                         ..default()
                     }),
                     ..default()
                 }),
-                ServerPlugins {
-                    tick_duration: Duration::from_secs_f64(1.0 / FIXED_TIMESTEP_HZ),
-                },
+                ServerPlugins { tick_duration },
                 MyServerPlugin,
+                StdinConsolePlugin,
             ));
+
+            app.insert_resource(LinkConditions::from(simulation))
+                .insert_resource(ServerTransportOptions {
+                    webtransport,
+                    websocket,
+                })
+                .insert_resource(RconPassword(rcon_password));
+
+            // LAN clients join over UDP
+            #[cfg(not(target_family = "wasm"))]
+            app.add_plugins(ServerDiscoveryPlugin)
+                .insert_resource(ServerInfo {
+                    name: server_name,
+                    port: SERVER_ADDR.port(),
+                });
+            #[cfg(target_family = "wasm")]
+            let _ = server_name;
         }
     }
 
@@ -99,6 +308,31 @@ fn main() {
     app.run();
 }
 
+/// Add the plugins of a client, in a window titled `title`.
+fn add_client(
+    app: &mut App,
+    title: &str,
+    tick_duration: Duration,
+    transport: Transport,
+    simulation: SimulationArgs,
+) {
+    app.add_plugins((
+        DefaultPlugins.set(WindowPlugin {
+            primary_window: Some(Window {
+                title: title.to_string(),
+                resolution: WINDOW_RESOLUTION.into(),
+                ..default()
+            }),
+            ..default()
+        }),
+        ClientPlugins { tick_duration },
+        MyClientPlugin,
+    ));
+
+    app.insert_resource(LinkConditions::from(simulation))
+        .insert_resource(transport);
+}
+
 fn spawn_camera(mut commands: Commands) {
-    commands.spawn(Camera2d);
+    commands.spawn((Camera2d, EditorCamera));
 }
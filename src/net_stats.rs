@@ -0,0 +1,264 @@
+//! Network statistics overlay for the client, toggled with F3.
+//!
+//! Shows RTT, jitter, packet loss, rollbacks and input delay, with small graphs of the
+//! recent RTT and rollback rate. The same numbers are logged once per second at debug level,
+//! e.g. with `RUST_LOG=bevy_project_template::net_stats=debug`.
+
+use core::time::Duration;
+use std::collections::VecDeque;
+
+use bevy::prelude::*;
+use lightyear::prelude::{client::InputTimeline, *};
+use lightyear::prediction::diagnostics::PredictionMetrics;
+
+const TOGGLE_KEY: KeyCode = KeyCode::F3;
+
+/// Number of samples displayed in a graph.
+const GRAPH_LEN: usize = 60;
+
+const GRAPH_HEIGHT: f32 = 40.;
+
+const SAMPLE_INTERVAL: Duration = Duration::from_millis(100);
+
+const LOG_INTERVAL: Duration = Duration::from_secs(1);
+
+pub struct NetStatsPlugin;
+
+impl Plugin for NetStatsPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<NetStats>()
+            .init_resource::<NetStatsTimers>()
+            .add_systems(Startup, spawn_net_stats_hud)
+            .add_systems(
+                Update,
+                (
+                    toggle_net_stats_hud,
+                    sample_net_stats,
+                    log_net_stats,
+                    update_net_stats_text,
+                    update_graphs,
+                )
+                    .chain(),
+            );
+    }
+}
+
+/// Latest network statistics of the client, only written when a sample is taken.
+#[derive(Resource, Default)]
+struct NetStats {
+    rtt: Duration,
+    jitter: Duration,
+    packet_loss: f32,
+    rollbacks: u32,
+    rollback_ticks: u32,
+    input_delay: u16,
+    /// Rollbacks since the previous sample.
+    rollbacks_per_sample: u32,
+}
+
+/// Kept apart from [`NetStats`], which would otherwise change every frame.
+#[derive(Resource)]
+struct NetStatsTimers {
+    sample: Timer,
+    log: Timer,
+}
+
+impl Default for NetStatsTimers {
+    fn default() -> Self {
+        Self {
+            sample: Timer::new(SAMPLE_INTERVAL, TimerMode::Repeating),
+            log: Timer::new(LOG_INTERVAL, TimerMode::Repeating),
+        }
+    }
+}
+
+#[derive(Component)]
+struct NetStatsHud;
+
+#[derive(Component)]
+struct NetStatsText;
+
+/// Which statistic is displayed in a graph.
+#[derive(Clone, Copy)]
+enum GraphKind {
+    Rtt,
+    Rollbacks,
+}
+
+impl GraphKind {
+    fn label(self) -> &'static str {
+        match self {
+            GraphKind::Rtt => "RTT",
+            GraphKind::Rollbacks => "Rollbacks",
+        }
+    }
+}
+
+/// Bar graph of the last [`GRAPH_LEN`] samples. Each child of the entity is one bar.
+#[derive(Component)]
+struct Graph {
+    kind: GraphKind,
+    samples: VecDeque<f32>,
+}
+
+fn spawn_net_stats_hud(mut commands: Commands) {
+    commands
+        .spawn((
+            Name::new("Network statistics"),
+            NetStatsHud,
+            Node {
+                position_type: PositionType::Absolute,
+                top: Val::Px(8.),
+                right: Val::Px(8.),
+                flex_direction: FlexDirection::Column,
+                row_gap: Val::Px(4.),
+                padding: UiRect::all(Val::Px(6.)),
+                ..default()
+            },
+            BackgroundColor(Color::srgba(0., 0., 0., 0.6)),
+            Visibility::Hidden,
+        ))
+        .with_children(|parent| {
+            parent.spawn((
+                NetStatsText,
+                Text::default(),
+                TextFont {
+                    font_size: 12.,
+                    ..default()
+                },
+            ));
+
+            for kind in [GraphKind::Rtt, GraphKind::Rollbacks] {
+                parent.spawn((
+                    Text::new(kind.label()),
+                    TextFont {
+                        font_size: 10.,
+                        ..default()
+                    },
+                ));
+                parent
+                    .spawn((
+                        Graph {
+                            kind,
+                            samples: VecDeque::from(vec![0.; GRAPH_LEN]),
+                        },
+                        Node {
+                            height: Val::Px(GRAPH_HEIGHT),
+                            align_items: AlignItems::FlexEnd,
+                            ..default()
+                        },
+                    ))
+                    .with_children(|graph| {
+                        for _ in 0..GRAPH_LEN {
+                            graph.spawn((
+                                Node {
+                                    width: Val::Px(3.),
+                                    height: Val::Px(0.),
+                                    ..default()
+                                },
+                                BackgroundColor(Color::srgb(0.3, 0.9, 0.4)),
+                            ));
+                        }
+                    });
+            }
+        });
+}
+
+fn toggle_net_stats_hud(
+    keyboard: Res<ButtonInput<KeyCode>>,
+    mut hud: Single<&mut Visibility, With<NetStatsHud>>,
+) {
+    if keyboard.just_pressed(TOGGLE_KEY) {
+        hud.toggle_inherited_visible();
+    }
+}
+
+fn sample_net_stats(
+    time: Res<Time>,
+    mut timers: ResMut<NetStatsTimers>,
+    mut stats: ResMut<NetStats>,
+    client: Single<(&Link, &InputTimeline), With<Client>>,
+    prediction_metrics: Option<Res<PredictionMetrics>>,
+) {
+    if !timers.sample.tick(time.delta()).just_finished() {
+        return;
+    }
+
+    let (link, input_timeline) = client.into_inner();
+
+    stats.rtt = link.stats.rtt;
+    stats.jitter = link.stats.jitter;
+    stats.packet_loss = link.stats.packet_loss;
+    stats.input_delay = input_timeline.input_delay();
+
+    if let Some(metrics) = prediction_metrics {
+        stats.rollbacks_per_sample = metrics.rollbacks.saturating_sub(stats.rollbacks);
+        stats.rollbacks = metrics.rollbacks;
+        stats.rollback_ticks = metrics.rollback_ticks;
+    }
+}
+
+fn log_net_stats(time: Res<Time>, mut timers: ResMut<NetStatsTimers>, stats: Res<NetStats>) {
+    if !timers.log.tick(time.delta()).just_finished() {
+        return;
+    }
+
+    debug!(
+        rtt = ?stats.rtt,
+        jitter = ?stats.jitter,
+        packet_loss = stats.packet_loss,
+        rollbacks = stats.rollbacks,
+        rollback_ticks = stats.rollback_ticks,
+        input_delay = stats.input_delay,
+        "Network statistics"
+    );
+}
+
+fn update_net_stats_text(
+    stats: Res<NetStats>,
+    mut text: Single<&mut Text, With<NetStatsText>>,
+) {
+    if !stats.is_changed() {
+        return;
+    }
+
+    text.0 = format!(
+        "RTT: {:.1} ms\nJitter: {:.1} ms\nPacket loss: {:.1} %\nRollbacks: {} ({} ticks)\nInput delay: {} ticks",
+        stats.rtt.as_secs_f32() * 1000.,
+        stats.jitter.as_secs_f32() * 1000.,
+        stats.packet_loss * 100.,
+        stats.rollbacks,
+        stats.rollback_ticks,
+        stats.input_delay,
+    );
+}
+
+/// Push a new sample into every graph each time the statistics are sampled.
+fn update_graphs(
+    stats: Res<NetStats>,
+    mut graph_q: Query<(&mut Graph, &Children)>,
+    mut bar_q: Query<&mut Node>,
+) {
+    if !stats.is_changed() {
+        return;
+    }
+
+    for (mut graph, bars) in graph_q.iter_mut() {
+        let sample = match graph.kind {
+            GraphKind::Rtt => stats.rtt.as_secs_f32() * 1000.,
+            GraphKind::Rollbacks => stats.rollbacks_per_sample as f32,
+        };
+
+        graph.samples.pop_front();
+        graph.samples.push_back(sample);
+
+        // scale the graph so that the highest sample fills it
+        let max = graph.samples.iter().copied().fold(1., f32::max);
+
+        for (sample, bar) in graph.samples.iter().zip(bars.iter()) {
+            if let Ok(mut node) = bar_q.get_mut(*bar) {
+                node.height = Val::Px(sample / max * GRAPH_HEIGHT);
+            }
+        }
+    }
+}
@@ -0,0 +1,172 @@
+//! Network tab of the editor: the links of this client or server, and how each entity is
+//! replicated, to see what the netcode is doing without reading logs.
+
+use avian2d::prelude::Position;
+use bevy::prelude::*;
+use egui_dock::egui;
+use lightyear::{
+    prediction::predicted_history::PredictionHistory,
+    prelude::{server::ClientOf, *},
+};
+
+/// Content of the Network tab, returns the entity clicked to select it.
+pub fn network_ui(ui: &mut egui::Ui, world: &mut World) -> Option<Entity> {
+    let mut clicked = None;
+
+    egui::ScrollArea::vertical().show(ui, |ui| {
+        ui.heading("Links");
+        links_ui(ui, world, &mut clicked);
+
+        ui.separator();
+        ui.heading("Replicated entities");
+        replication_ui(ui, world, &mut clicked);
+    });
+
+    clicked
+}
+
+/// Label of an entity, which selects it when clicked.
+fn entity_label(
+    ui: &mut egui::Ui,
+    entity: Entity,
+    name: Option<&Name>,
+    clicked: &mut Option<Entity>,
+) {
+    let text = match name {
+        Some(name) => format!("{name} ({entity})"),
+        None => entity.to_string(),
+    };
+    if ui.selectable_label(false, text).clicked() {
+        *clicked = Some(entity);
+    }
+}
+
+fn links_ui(ui: &mut egui::Ui, world: &mut World, clicked: &mut Option<Entity>) {
+    let mut link_q = world.query_filtered::<(
+        Entity,
+        Option<&Name>,
+        Has<Client>,
+        Has<ClientOf>,
+        Option<&RemoteId>,
+        Has<Connected>,
+        Has<Disconnected>,
+        Option<&Link>,
+    ), Or<(With<Client>, With<ClientOf>)>>();
+
+    if link_q.iter(world).next().is_none() {
+        ui.label("No links");
+        return;
+    }
+
+    egui::Grid::new("network_links")
+        .striped(true)
+        .num_columns(5)
+        .show(ui, |ui| {
+            ui.strong("Entity");
+            ui.strong("Kind");
+            ui.strong("Remote");
+            ui.strong("State");
+            ui.strong("RTT");
+            ui.end_row();
+
+            for (entity, name, client, client_of, remote_id, connected, disconnected, link) in
+                link_q.iter(world)
+            {
+                entity_label(ui, entity, name, clicked);
+                ui.label(match (client, client_of) {
+                    (true, _) => "Client",
+                    (_, true) => "ClientOf",
+                    _ => "",
+                });
+                ui.label(remote_id.map_or("-".to_string(), |id| format!("{:?}", id.0)));
+                ui.label(if connected {
+                    "Connected"
+                } else if disconnected {
+                    "Disconnected"
+                } else {
+                    "Connecting"
+                });
+                ui.label(link.map_or("-".to_string(), |link| {
+                    format!("{} ms", link.stats.rtt.as_millis())
+                }));
+                ui.end_row();
+            }
+        });
+}
+
+fn replication_ui(ui: &mut egui::Ui, world: &mut World, clicked: &mut Option<Entity>) {
+    let mut entity_q = world.query_filtered::<(
+        Entity,
+        Option<&Name>,
+        Has<Replicate>,
+        Option<&Predicted>,
+        Has<Interpolated>,
+        Option<&Confirmed>,
+        Has<PreSpawned>,
+        Option<&PredictionHistory<Position>>,
+    ), Or<(
+        With<Replicate>,
+        With<Predicted>,
+        With<Interpolated>,
+        With<Confirmed>,
+        With<PreSpawned>,
+    )>>();
+    let mut confirmed_q = world.query::<&Confirmed>();
+
+    let mut rows: Vec<_> = entity_q.iter(world).collect();
+    if rows.is_empty() {
+        ui.label("No replicated entities");
+        return;
+    }
+    rows.sort_by_key(|(entity, ..)| *entity);
+
+    egui::Grid::new("network_replication")
+        .striped(true)
+        .num_columns(4)
+        .show(ui, |ui| {
+            ui.strong("Entity");
+            ui.strong("Status");
+            ui.strong("Prediction history");
+            ui.strong("Last confirmed tick");
+            ui.end_row();
+
+            for (
+                entity,
+                name,
+                replicate,
+                predicted,
+                interpolated,
+                confirmed,
+                pre_spawned,
+                history,
+            ) in rows
+            {
+                entity_label(ui, entity, name, clicked);
+
+                let status: Vec<&str> = [
+                    (replicate, "Replicate"),
+                    (predicted.is_some(), "Predicted"),
+                    (interpolated, "Interpolated"),
+                    (confirmed.is_some(), "Confirmed"),
+                    (pre_spawned, "PreSpawned"),
+                ]
+                .into_iter()
+                .filter_map(|(has, label)| has.then_some(label))
+                .collect();
+                ui.label(status.join(", "));
+
+                ui.label(history.map_or("-".to_string(), |history| history.len().to_string()));
+
+                // predicted entities are updated from their confirmed entity
+                let confirmed = confirmed.or_else(|| {
+                    predicted
+                        .and_then(|predicted| predicted.confirmed_entity)
+                        .and_then(|confirmed| confirmed_q.get(world, confirmed).ok())
+                });
+                ui.label(
+                    confirmed.map_or("-".to_string(), |confirmed| format!("{:?}", confirmed.tick)),
+                );
+                ui.end_row();
+            }
+        });
+}
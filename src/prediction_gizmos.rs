@@ -0,0 +1,136 @@
+//! Draws the confirmed, predicted and interpolated copies of replicated entities side by side,
+//! toggled with F5, on top of the physics debug rendering.
+//!
+//! Each copy gets a marker at its `Position`, pointing along its `Rotation`, and a trail of its
+//! positions during the last [`TRAIL_TICKS`] ticks.
+
+use std::collections::VecDeque;
+
+use avian2d::prelude::{Position, Rotation};
+use bevy::prelude::*;
+use lightyear::{prediction::rollback::is_in_rollback, prelude::*};
+
+const TOGGLE_KEY: KeyCode = KeyCode::F5;
+
+/// Number of ticks kept in a trail.
+const TRAIL_TICKS: usize = 64;
+
+const MARKER_RADIUS: f32 = 6.;
+
+const CONFIRMED_COLOR: Color = Color::srgb(0.2, 0.9, 0.3);
+
+const PREDICTED_COLOR: Color = Color::srgb(0.3, 0.6, 1.0);
+
+const INTERPOLATED_COLOR: Color = Color::srgb(1.0, 0.5, 0.2);
+
+pub struct PredictionGizmosPlugin;
+
+impl Plugin for PredictionGizmosPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<PredictionGizmos>()
+            .add_systems(Update, toggle_prediction_gizmos)
+            .add_systems(
+                FixedLast,
+                // ticks resimulated during a rollback were already recorded
+                record_trails
+                    .run_if(|gizmos: Res<PredictionGizmos>| gizmos.enabled)
+                    .run_if(not(is_in_rollback)),
+            )
+            .add_systems(
+                Update,
+                draw_prediction_gizmos
+                    .after(toggle_prediction_gizmos)
+                    .run_if(|gizmos: Res<PredictionGizmos>| gizmos.enabled),
+            );
+    }
+}
+
+#[derive(Resource, Default)]
+struct PredictionGizmos {
+    enabled: bool,
+}
+
+/// Positions of an entity during the last [`TRAIL_TICKS`] ticks, oldest first.
+#[derive(Component, Default)]
+struct Trail(VecDeque<Vec2>);
+
+type Copies = Or<(With<Confirmed>, With<Predicted>, With<Interpolated>)>;
+
+fn toggle_prediction_gizmos(
+    mut commands: Commands,
+    keyboard: Res<ButtonInput<KeyCode>>,
+    mut gizmos: ResMut<PredictionGizmos>,
+    trail_q: Query<Entity, With<Trail>>,
+) {
+    if !keyboard.just_pressed(TOGGLE_KEY) {
+        return;
+    }
+    gizmos.enabled = !gizmos.enabled;
+    // start with fresh trails when enabled again
+    if !gizmos.enabled {
+        for entity in trail_q.iter() {
+            commands.entity(entity).remove::<Trail>();
+        }
+    }
+}
+
+fn record_trails(
+    mut commands: Commands,
+    mut copy_q: Query<(Entity, &Position, Option<&mut Trail>), Copies>,
+) {
+    for (entity, position, trail) in copy_q.iter_mut() {
+        match trail {
+            Some(mut trail) => {
+                if trail.0.len() >= TRAIL_TICKS {
+                    trail.0.pop_front();
+                }
+                trail.0.push_back(position.0);
+            }
+            None => {
+                commands
+                    .entity(entity)
+                    .insert(Trail(VecDeque::from([position.0])));
+            }
+        }
+    }
+}
+
+fn draw_prediction_gizmos(
+    copy_q: Query<
+        (
+            &Position,
+            Option<&Rotation>,
+            Option<&Trail>,
+            Has<Confirmed>,
+            Has<Predicted>,
+        ),
+        Copies,
+    >,
+    mut gizmos: Gizmos,
+) {
+    for (position, rotation, trail, confirmed, predicted) in copy_q.iter() {
+        let color = if confirmed {
+            CONFIRMED_COLOR
+        } else if predicted {
+            PREDICTED_COLOR
+        } else {
+            INTERPOLATED_COLOR
+        };
+
+        let rotation = rotation.copied().unwrap_or_default();
+        gizmos.circle_2d(
+            Isometry2d::from_translation(position.0),
+            MARKER_RADIUS,
+            color,
+        );
+        gizmos.line_2d(
+            position.0,
+            position.0 + Vec2::from_angle(rotation.as_radians()) * MARKER_RADIUS * 2.,
+            color,
+        );
+
+        if let Some(trail) = trail {
+            gizmos.linestrip_2d(trail.0.iter().copied(), color.with_alpha(0.5));
+        }
+    }
+}
@@ -4,16 +4,127 @@ use avian2d::prelude::{
 };
 use bevy::prelude::*;
 use leafwing_input_manager::prelude::*;
-use lightyear::prelude::*;
+use lightyear::{
+    prediction::{predicted_history::PredictionHistory, rollback::RollbackSet},
+    prelude::*,
+};
 use lightyear_frame_interpolation::{FrameInterpolate, FrameInterpolationPlugin};
 use serde::{Deserialize, Serialize};
+use std::{net::SocketAddr, sync::RwLock};
+
+use crate::{
+    config,
+    cvars::CvarValue,
+    level::Level,
+    rollback_diagnostics::{Misprediction, RollbackRecord},
+};
+
+/// Version of the protocol, part of the [`ProtocolHash`].
+///
+/// Must be increased when the content of a registered type changes.
+pub const PROTOCOL_VERSION: u32 = 2;
+
+/// Configuration file of the [`RollbackTolerances`].
+const ROLLBACK_TOLERANCES_FILE: &str = "rollback_tolerances.ron";
+
+/// Register a channel, message or component with lightyear and push its name to `$types`,
+/// from which the [`ProtocolHash`] is computed, e.g.
+/// `register!(types, app.add_message::<ClientHello>())`.
+///
+/// The registries of lightyear only keep the `TypeId` of registered types, which differs
+/// between compilers, so names are recorded as the types are registered.
+macro_rules! register {
+    ($types:ident, $app:ident.$register:ident::<$ty:ty>($($arg:expr)?)) => {{
+        $types.push(core::any::type_name::<$ty>());
+        $app.$register::<$ty>($($arg)?)
+    }};
+}
 
 pub struct ProtocolPlugin;
 
 impl Plugin for ProtocolPlugin {
     fn build(&self, app: &mut App) {
-        app.register_type::<(PlayerAction, PlayerId)>();
+        app.register_type::<(
+            PlayerAction,
+            PlayerId,
+            Team,
+            PlayerStats,
+            RollbackTolerances,
+        )>();
+
+        app.insert_resource(config::load_or_default::<RollbackTolerances>(
+            ROLLBACK_TOLERANCES_FILE,
+        ))
+        .add_systems(
+            PreUpdate,
+            sync_rollback_tolerances
+                .run_if(resource_changed::<RollbackTolerances>)
+                .before(RollbackSet::Check),
+        );
+
+        app.add_event::<RollbackRecord>().add_systems(
+            PreUpdate,
+            (
+                check_misprediction::<Position>,
+                check_misprediction::<Rotation>,
+                check_misprediction::<LinearVelocity>,
+                check_misprediction::<AngularVelocity>,
+                check_misprediction::<ExternalForce>,
+                check_misprediction::<ExternalImpulse>,
+                check_misprediction::<ComputedMass>,
+            )
+                .before(RollbackSet::Check),
+        );
+
+        // names of the registered types, in registration order
+        let mut types = Vec::new();
+
+        register!(
+            types,
+            app.add_channel::<ChatChannel>(ChannelSettings {
+                mode: ChannelMode::OrderedReliable(ReliableSettings::default()),
+                ..default()
+            })
+        )
+        .add_direction(NetworkDirection::Bidirectional);
+
+        register!(
+            types,
+            app.add_channel::<ControlChannel>(ChannelSettings {
+                mode: ChannelMode::OrderedReliable(ReliableSettings::default()),
+                ..default()
+            })
+        )
+        .add_direction(NetworkDirection::Bidirectional);
+
+        register!(types, app.add_message::<ClientHello>())
+            .add_direction(NetworkDirection::ClientToServer);
+
+        register!(types, app.add_message::<ConnectionRefused>())
+            .add_direction(NetworkDirection::ServerToClient);
 
+        register!(types, app.add_message::<Queued>())
+            .add_direction(NetworkDirection::ServerToClient);
+
+        register!(types, app.add_message::<ChangeLevel>())
+            .add_direction(NetworkDirection::ServerToClient);
+
+        register!(types, app.add_message::<RemoteCommand>())
+            .add_direction(NetworkDirection::ClientToServer);
+
+        register!(types, app.add_message::<CommandOutput>())
+            .add_direction(NetworkDirection::ServerToClient);
+
+        register!(types, app.add_message::<CvarSync>())
+            .add_direction(NetworkDirection::ServerToClient);
+
+        register!(types, app.add_message::<ChatMessage>())
+            .add_direction(NetworkDirection::ClientToServer);
+
+        register!(types, app.add_message::<ChatBroadcast>())
+            .add_direction(NetworkDirection::ServerToClient);
+
+        types.push(core::any::type_name::<PlayerAction>());
         app.add_plugins(input::leafwing::InputPlugin::<PlayerAction> {
             config: input::InputConfig::<PlayerAction> {
                 // enable lag compensation; the input messages sent to the server will include the
@@ -29,60 +140,80 @@ impl Plugin for ProtocolPlugin {
         //     .add_interpolation(InterpolationMode::Full)
         //     .add_interpolation_fn(transform_interpolation_fn);
 
-        app.register_component::<Player>()
+        register!(types, app.register_component::<Player>())
             .add_prediction(PredictionMode::Once)
             .add_interpolation(InterpolationMode::Once);
 
-        app.register_component::<PlayerId>()
+        register!(types, app.register_component::<PlayerId>())
             .add_prediction(PredictionMode::Once)
             .add_interpolation(InterpolationMode::Once);
 
-        app.register_component::<Name>()
+        // Name can be changed by the server after spawn (e.g. from the inspector),
+        // so it has to be synced to the predicted/interpolated entities on every update.
+        register!(types, app.register_component::<Name>())
+            .add_prediction(PredictionMode::Simple)
+            .add_interpolation(InterpolationMode::Simple);
+
+        register!(types, app.register_component::<Team>())
             .add_prediction(PredictionMode::Once)
             .add_interpolation(InterpolationMode::Once);
 
-        app.register_component::<RigidBody>()
+        // Only displayed in the scoreboard, which reads it from the confirmed entity.
+        register!(types, app.register_component::<PlayerStats>());
+
+        register!(types, app.register_component::<RigidBody>())
             .add_prediction(PredictionMode::Once);
 
-        app.register_component::<Bullet>()
+        register!(types, app.register_component::<Bullet>())
             .add_prediction(PredictionMode::Once)
             .add_interpolation(InterpolationMode::Once);
 
-        app.register_component::<Ball>()
+        register!(types, app.register_component::<Ball>())
             .add_prediction(PredictionMode::Once)
             .add_interpolation(InterpolationMode::Once);
 
-        app.register_component::<avian2d::prelude::Position>()
-            .add_prediction(PredictionMode::Full)
-            .add_should_rollback(position_should_rollback)
-            .add_interpolation(InterpolationMode::Full)
-            .add_linear_interpolation_fn()
-            .add_linear_correction_fn();
-
-        app.register_component::<avian2d::prelude::Rotation>()
-            .add_prediction(PredictionMode::Full)
-            .add_should_rollback(rotation_should_rollback)
-            .add_interpolation(InterpolationMode::Full)
-            .add_linear_interpolation_fn()
-            .add_linear_correction_fn();
+        register!(
+            types,
+            app.register_component::<avian2d::prelude::Position>()
+        )
+        .add_prediction(PredictionMode::Full)
+        .add_should_rollback(exceeds_tolerance::<Position>)
+        .add_interpolation(InterpolationMode::Full)
+        .add_linear_interpolation_fn()
+        .add_linear_correction_fn();
+
+        register!(
+            types,
+            app.register_component::<avian2d::prelude::Rotation>()
+        )
+        .add_prediction(PredictionMode::Full)
+        .add_should_rollback(exceeds_tolerance::<Rotation>)
+        .add_interpolation(InterpolationMode::Full)
+        .add_linear_interpolation_fn()
+        .add_linear_correction_fn();
 
         // Fully replicated, but not visual, so no need for lerp/corrections:
         // NOTE: interpolation/correction is only needed for components that are visually displayed!
         // we still need prediction to be able to correctly predict the physics on the client
-        app.register_component::<LinearVelocity>()
-            .add_prediction(PredictionMode::Full);
+        register!(types, app.register_component::<LinearVelocity>())
+            .add_prediction(PredictionMode::Full)
+            .add_should_rollback(exceeds_tolerance::<LinearVelocity>);
 
-        app.register_component::<AngularVelocity>()
-            .add_prediction(PredictionMode::Full);
+        register!(types, app.register_component::<AngularVelocity>())
+            .add_prediction(PredictionMode::Full)
+            .add_should_rollback(exceeds_tolerance::<AngularVelocity>);
 
-        app.register_component::<ExternalForce>()
-            .add_prediction(PredictionMode::Full);
+        register!(types, app.register_component::<ExternalForce>())
+            .add_prediction(PredictionMode::Full)
+            .add_should_rollback(exceeds_tolerance::<ExternalForce>);
 
-        app.register_component::<ExternalImpulse>()
-            .add_prediction(PredictionMode::Full);
+        register!(types, app.register_component::<ExternalImpulse>())
+            .add_prediction(PredictionMode::Full)
+            .add_should_rollback(exceeds_tolerance::<ExternalImpulse>);
 
-        app.register_component::<ComputedMass>()
-            .add_prediction(PredictionMode::Full);
+        register!(types, app.register_component::<ComputedMass>())
+            .add_prediction(PredictionMode::Full)
+            .add_should_rollback(exceeds_tolerance::<ComputedMass>);
 
         // Set up visual interp plugins for Position/Rotation. Position/Rotation is updated in FixedUpdate
         // by the physics plugin so we make sure that in PostUpdate we interpolate it
@@ -102,14 +233,21 @@ impl Plugin for ProtocolPlugin {
         app.world_mut()
             .resource_mut::<InterpolationRegistry>()
             .set_interpolation_mode::<Transform>(InterpolationMode::None);
+
+        app.insert_resource(ProtocolHash::new(&types));
     }
 }
 
 /// TODO: Remove this. Used just to give argument to client from CLI.
 /// Inserted when CLI is parsed.
-#[derive(Component)]
+#[derive(Component, Clone)]
 pub struct CliClientOptions {
     pub id: u64,
+    /// Display name requested by the player.
+    pub name: String,
+    pub server_addr: SocketAddr,
+    /// Digest of the server certificate, required by WebTransport.
+    pub certificate_digest: String,
 }
 
 #[derive(Component, Serialize, Deserialize, Debug, Reflect, PartialEq, Clone)]
@@ -128,6 +266,29 @@ impl Player {
 #[derive(Component, Serialize, Deserialize, Debug, Reflect, PartialEq, Clone, Copy)]
 pub struct PlayerId(pub PeerId);
 
+/// Per-player statistics. Updated by the server.
+#[derive(Component, Serialize, Deserialize, Debug, Default, Reflect, PartialEq, Clone, Copy)]
+pub struct PlayerStats {
+    pub kills: u32,
+    pub deaths: u32,
+    pub score: u32,
+    /// Round trip time between the server and the client, in milliseconds.
+    pub ping: u32,
+}
+
+/// Team of the player. Used to route team chat, and bullets don't hit teammates.
+#[derive(Component, Serialize, Deserialize, Debug, Reflect, PartialEq, Eq, Clone, Copy)]
+pub struct Team(pub u8);
+
+impl Team {
+    pub const COUNT: u8 = 2;
+
+    /// Players are split between teams based on their client id.
+    pub fn from_peer(peer: PeerId) -> Self {
+        Self((peer.to_bits() % Self::COUNT as u64) as u8)
+    }
+}
+
 #[derive(Component)]
 #[require(Name::new("Wall"), RigidBody::Static, Collider::rectangle(40., 40.))]
 pub struct Wall;
@@ -156,18 +317,305 @@ pub enum PlayerAction {
     Shoot,
 }
 
-fn position_should_rollback(
-    this: &avian2d::prelude::Position,
-    that: &avian2d::prelude::Position,
-) -> bool {
-    (this.0 - that.0).length() >= 0.01
+/// Reliable ordered channel for connection control messages.
+pub struct ControlChannel;
+
+/// First message sent by a client once connected.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+pub struct ClientHello {
+    /// Desired display name. The server may change it to keep names valid and unique.
+    pub name: String,
+    /// [`ProtocolHash`] of the client, the server refuses clients with a different protocol
+    /// before admitting them.
+    pub protocol_hash: u64,
+}
+
+/// Sent by the server right before disconnecting a client it does not accept.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+pub struct ConnectionRefused {
+    /// Human-readable reason, displayed by the client.
+    pub reason: String,
+}
+
+/// Sent by a full server to clients waiting for a free slot, whenever their position changes.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+pub struct Queued {
+    /// Starts at 1 for the next client to be admitted.
+    pub position: u32,
+}
+
+/// Sent by the server to make clients load another level, or the saved version of the current one.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+pub struct ChangeLevel {
+    pub name: String,
+    pub level: Level,
+}
+
+/// Admin command sent by a client, executed if the password matches the server's.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+pub struct RemoteCommand {
+    pub password: String,
+    pub command: String,
+}
+
+/// Output of a [`RemoteCommand`], sent back to the client which sent it.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+pub struct CommandOutput {
+    pub text: String,
+}
+
+/// Values of the replicated cvars, sent by the server to clients.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+pub struct CvarSync {
+    pub values: Vec<(String, CvarValue)>,
+}
+
+/// Hash of the channels, messages, inputs and components registered by [`ProtocolPlugin`],
+/// in registration order.
+///
+/// Lightyear identifies them by their registration index, so builds registering them
+/// differently can connect to each other but misread every packet. Includes
+/// [`PROTOCOL_VERSION`] to also detect changes to the content of messages.
+#[derive(Resource, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ProtocolHash(pub u64);
+
+impl ProtocolHash {
+    fn new(types: &[&str]) -> Self {
+        // FNV-1a, which unlike `DefaultHasher` is the same for every build
+        const FNV_OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+        const FNV_PRIME: u64 = 0x0000_0100_0000_01b3;
+
+        let hash = PROTOCOL_VERSION
+            .to_le_bytes()
+            .into_iter()
+            .chain(types.iter().flat_map(|name| name.bytes().chain([0])))
+            .fold(FNV_OFFSET, |hash, byte| {
+                (hash ^ u64::from(byte)).wrapping_mul(FNV_PRIME)
+            });
+        Self(hash)
+    }
+}
+
+/// Reliable ordered channel for chat messages.
+pub struct ChatChannel;
+
+/// Who should receive a chat message.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone, Copy)]
+pub enum ChatScope {
+    All,
+    Team,
 }
 
-fn rotation_should_rollback(
-    this: &avian2d::prelude::Rotation,
-    that: &avian2d::prelude::Rotation,
-) -> bool {
-    this.angle_between(*that) >= 0.01
+/// Chat message typed by a client, sent to the server.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+pub struct ChatMessage {
+    pub scope: ChatScope,
+    pub text: String,
+}
+
+/// Chat message validated by the server and rebroadcast to clients.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+pub struct ChatBroadcast {
+    pub author: String,
+    pub scope: ChatScope,
+    pub text: String,
+}
+
+/// How far the predicted value of a component may be from the confirmed one before a rollback happens.
+///
+/// Loaded from `config/rollback_tolerances.ron` and can be tweaked at runtime from the editor.
+#[derive(Resource, Serialize, Deserialize, Debug, Reflect, Clone, Copy, PartialEq)]
+#[reflect(Resource)]
+#[serde(default)]
+pub struct RollbackTolerances {
+    /// Distance between positions.
+    pub position: f32,
+    /// Angle between rotations, in radians.
+    pub rotation: f32,
+    /// Length of the difference between linear velocities.
+    pub linear_velocity: f32,
+    /// Difference between angular velocities, in radians per second.
+    pub angular_velocity: f32,
+    /// Length of the difference between external forces.
+    pub external_force: f32,
+    /// Length of the difference between external impulses.
+    pub external_impulse: f32,
+    /// Difference between masses.
+    pub computed_mass: f32,
+}
+
+impl RollbackTolerances {
+    const DEFAULT: Self = Self {
+        position: 0.01,
+        rotation: 0.01,
+        linear_velocity: 0.1,
+        angular_velocity: 0.01,
+        external_force: 0.1,
+        external_impulse: 0.1,
+        computed_mass: 0.01,
+    };
+}
+
+impl Default for RollbackTolerances {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+/// Copy of the [`RollbackTolerances`] resource used by the `should_rollback` functions,
+/// which are plain function pointers without world access.
+static ROLLBACK_TOLERANCES: RwLock<RollbackTolerances> = RwLock::new(RollbackTolerances::DEFAULT);
+
+fn sync_rollback_tolerances(tolerances: Res<RollbackTolerances>) {
+    if let Ok(mut current) = ROLLBACK_TOLERANCES.write() {
+        *current = *tolerances;
+    }
+}
+
+fn rollback_tolerances() -> RollbackTolerances {
+    ROLLBACK_TOLERANCES
+        .read()
+        .map_or(RollbackTolerances::DEFAULT, |tolerances| *tolerances)
+}
+
+/// `should_rollback` function of the predicted components: `this` is the confirmed value
+/// received from the server, `that` is the predicted one.
+fn exceeds_tolerance<C: ToleratedComponent>(this: &C, that: &C) -> bool {
+    C::misprediction(that, this).delta() >= C::tolerance(&rollback_tolerances())
+}
+
+/// Predicted component compared with the value received from the server within a tolerance.
+trait ToleratedComponent: Component + Clone {
+    fn tolerance(tolerances: &RollbackTolerances) -> f32;
+
+    fn misprediction(predicted: &Self, confirmed: &Self) -> Misprediction;
+}
+
+impl ToleratedComponent for Position {
+    fn tolerance(tolerances: &RollbackTolerances) -> f32 {
+        tolerances.position
+    }
+
+    fn misprediction(predicted: &Self, confirmed: &Self) -> Misprediction {
+        Misprediction::Position {
+            predicted: *predicted,
+            confirmed: *confirmed,
+        }
+    }
+}
+
+impl ToleratedComponent for Rotation {
+    fn tolerance(tolerances: &RollbackTolerances) -> f32 {
+        tolerances.rotation
+    }
+
+    fn misprediction(predicted: &Self, confirmed: &Self) -> Misprediction {
+        Misprediction::Rotation {
+            predicted: *predicted,
+            confirmed: *confirmed,
+        }
+    }
+}
+
+impl ToleratedComponent for LinearVelocity {
+    fn tolerance(tolerances: &RollbackTolerances) -> f32 {
+        tolerances.linear_velocity
+    }
+
+    fn misprediction(predicted: &Self, confirmed: &Self) -> Misprediction {
+        Misprediction::LinearVelocity {
+            predicted: *predicted,
+            confirmed: *confirmed,
+        }
+    }
+}
+
+impl ToleratedComponent for AngularVelocity {
+    fn tolerance(tolerances: &RollbackTolerances) -> f32 {
+        tolerances.angular_velocity
+    }
+
+    fn misprediction(predicted: &Self, confirmed: &Self) -> Misprediction {
+        Misprediction::AngularVelocity {
+            predicted: *predicted,
+            confirmed: *confirmed,
+        }
+    }
+}
+
+impl ToleratedComponent for ExternalForce {
+    fn tolerance(tolerances: &RollbackTolerances) -> f32 {
+        tolerances.external_force
+    }
+
+    fn misprediction(predicted: &Self, confirmed: &Self) -> Misprediction {
+        Misprediction::ExternalForce {
+            predicted: *predicted,
+            confirmed: *confirmed,
+        }
+    }
+}
+
+impl ToleratedComponent for ExternalImpulse {
+    fn tolerance(tolerances: &RollbackTolerances) -> f32 {
+        tolerances.external_impulse
+    }
+
+    fn misprediction(predicted: &Self, confirmed: &Self) -> Misprediction {
+        Misprediction::ExternalImpulse {
+            predicted: *predicted,
+            confirmed: *confirmed,
+        }
+    }
+}
+
+impl ToleratedComponent for ComputedMass {
+    fn tolerance(tolerances: &RollbackTolerances) -> f32 {
+        tolerances.computed_mass
+    }
+
+    fn misprediction(predicted: &Self, confirmed: &Self) -> Misprediction {
+        Misprediction::ComputedMass {
+            predicted: *predicted,
+            confirmed: *confirmed,
+        }
+    }
+}
+
+/// Report every value of `C` received from the server that is further than its tolerance from
+/// the value predicted for the same tick, which makes lightyear roll back.
+///
+/// Runs before the rollback check of lightyear, which drops the prediction history up to the
+/// confirmed tick.
+fn check_misprediction<C: ToleratedComponent>(
+    tolerances: Res<RollbackTolerances>,
+    confirmed_q: Query<(&Confirmed, &C), Changed<C>>,
+    history_q: Query<&PredictionHistory<C>>,
+    mut mispredictions: EventWriter<RollbackRecord>,
+) {
+    for (confirmed, value) in confirmed_q.iter() {
+        let Some(entity) = confirmed.predicted else {
+            continue;
+        };
+        let Some(predicted) = history_q
+            .get(entity)
+            .ok()
+            .and_then(|history| history.get(confirmed.tick))
+        else {
+            continue;
+        };
+
+        let misprediction = C::misprediction(predicted, value);
+        if misprediction.delta() < C::tolerance(&tolerances) {
+            continue;
+        }
+
+        mispredictions.write(RollbackRecord {
+            tick: confirmed.tick,
+            entity,
+            misprediction,
+        });
+    }
 }
 
 /// Add the VisualInterpolateStatus::<Transform> component to non-floor entities with
@@ -0,0 +1,286 @@
+//! Records every misprediction that caused a rollback on the client.
+//!
+//! Mispredictions are reported as [`RollbackRecord`] events by the protocol, when the values
+//! received from the server are compared with the predicted history. They are counted in Bevy
+//! [`Diagnostics`] and kept for a CSV dump (F4).
+
+use std::{collections::VecDeque, fmt::Write as _, fs, path::Path};
+
+use avian2d::prelude::{
+    AngularVelocity, ComputedMass, ExternalForce, ExternalImpulse, LinearVelocity, Position,
+    Rotation,
+};
+use bevy::{
+    diagnostic::{Diagnostic, DiagnosticPath, Diagnostics, RegisterDiagnostic},
+    prelude::*,
+};
+use lightyear::prelude::*;
+
+const DUMP_KEY: KeyCode = KeyCode::F4;
+
+const CSV_PATH: &str = "rollbacks.csv";
+
+/// Maximum number of records kept in memory.
+const MAX_RECORDS: usize = 10_000;
+
+pub const POSITION_ROLLBACKS: DiagnosticPath = DiagnosticPath::const_new("rollback/position");
+
+pub const ROTATION_ROLLBACKS: DiagnosticPath = DiagnosticPath::const_new("rollback/rotation");
+
+pub const LINEAR_VELOCITY_ROLLBACKS: DiagnosticPath =
+    DiagnosticPath::const_new("rollback/linear_velocity");
+
+pub const ANGULAR_VELOCITY_ROLLBACKS: DiagnosticPath =
+    DiagnosticPath::const_new("rollback/angular_velocity");
+
+pub const EXTERNAL_FORCE_ROLLBACKS: DiagnosticPath =
+    DiagnosticPath::const_new("rollback/external_force");
+
+pub const EXTERNAL_IMPULSE_ROLLBACKS: DiagnosticPath =
+    DiagnosticPath::const_new("rollback/external_impulse");
+
+pub const COMPUTED_MASS_ROLLBACKS: DiagnosticPath =
+    DiagnosticPath::const_new("rollback/computed_mass");
+
+/// Rollback counters, one per [`Misprediction`] variant.
+const ROLLBACK_DIAGNOSTICS: [DiagnosticPath; 7] = [
+    POSITION_ROLLBACKS,
+    ROTATION_ROLLBACKS,
+    LINEAR_VELOCITY_ROLLBACKS,
+    ANGULAR_VELOCITY_ROLLBACKS,
+    EXTERNAL_FORCE_ROLLBACKS,
+    EXTERNAL_IMPULSE_ROLLBACKS,
+    COMPUTED_MASS_ROLLBACKS,
+];
+
+pub struct RollbackDiagnosticsPlugin;
+
+impl Plugin for RollbackDiagnosticsPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<RollbackLog>();
+        for path in ROLLBACK_DIAGNOSTICS {
+            app.register_diagnostic(Diagnostic::new(path));
+        }
+        app.add_systems(Update, (collect_rollbacks, dump_rollbacks).chain());
+    }
+}
+
+/// Confirmed value received from the server and the value that was predicted for the same tick.
+#[derive(Clone, Copy, Debug)]
+pub enum Misprediction {
+    Position {
+        predicted: Position,
+        confirmed: Position,
+    },
+    Rotation {
+        predicted: Rotation,
+        confirmed: Rotation,
+    },
+    LinearVelocity {
+        predicted: LinearVelocity,
+        confirmed: LinearVelocity,
+    },
+    AngularVelocity {
+        predicted: AngularVelocity,
+        confirmed: AngularVelocity,
+    },
+    ExternalForce {
+        predicted: ExternalForce,
+        confirmed: ExternalForce,
+    },
+    ExternalImpulse {
+        predicted: ExternalImpulse,
+        confirmed: ExternalImpulse,
+    },
+    ComputedMass {
+        predicted: ComputedMass,
+        confirmed: ComputedMass,
+    },
+}
+
+impl Misprediction {
+    fn component(&self) -> &'static str {
+        match self {
+            Misprediction::Position { .. } => "Position",
+            Misprediction::Rotation { .. } => "Rotation",
+            Misprediction::LinearVelocity { .. } => "LinearVelocity",
+            Misprediction::AngularVelocity { .. } => "AngularVelocity",
+            Misprediction::ExternalForce { .. } => "ExternalForce",
+            Misprediction::ExternalImpulse { .. } => "ExternalImpulse",
+            Misprediction::ComputedMass { .. } => "ComputedMass",
+        }
+    }
+
+    /// Index of the counter of this kind of misprediction in [`ROLLBACK_DIAGNOSTICS`].
+    fn diagnostic_index(&self) -> usize {
+        match self {
+            Misprediction::Position { .. } => 0,
+            Misprediction::Rotation { .. } => 1,
+            Misprediction::LinearVelocity { .. } => 2,
+            Misprediction::AngularVelocity { .. } => 3,
+            Misprediction::ExternalForce { .. } => 4,
+            Misprediction::ExternalImpulse { .. } => 5,
+            Misprediction::ComputedMass { .. } => 6,
+        }
+    }
+
+    /// How far the predicted value is from the confirmed one.
+    pub fn delta(&self) -> f32 {
+        match self {
+            Misprediction::Position {
+                predicted,
+                confirmed,
+            } => predicted.distance(confirmed.0),
+            Misprediction::Rotation {
+                predicted,
+                confirmed,
+            } => predicted.angle_between(*confirmed).abs(),
+            Misprediction::LinearVelocity {
+                predicted,
+                confirmed,
+            } => predicted.distance(confirmed.0),
+            Misprediction::AngularVelocity {
+                predicted,
+                confirmed,
+            } => (predicted.0 - confirmed.0).abs(),
+            Misprediction::ExternalForce {
+                predicted,
+                confirmed,
+            } => predicted.force().distance(confirmed.force()),
+            Misprediction::ExternalImpulse {
+                predicted,
+                confirmed,
+            } => predicted.impulse().distance(confirmed.impulse()),
+            Misprediction::ComputedMass {
+                predicted,
+                confirmed,
+            } => (predicted.value() - confirmed.value()).abs(),
+        }
+    }
+
+    fn values(&self) -> (String, String) {
+        match self {
+            Misprediction::Position {
+                predicted,
+                confirmed,
+            } => (
+                format!("{} {}", predicted.x, predicted.y),
+                format!("{} {}", confirmed.x, confirmed.y),
+            ),
+            Misprediction::Rotation {
+                predicted,
+                confirmed,
+            } => (
+                predicted.as_radians().to_string(),
+                confirmed.as_radians().to_string(),
+            ),
+            Misprediction::LinearVelocity {
+                predicted,
+                confirmed,
+            } => (
+                format!("{} {}", predicted.x, predicted.y),
+                format!("{} {}", confirmed.x, confirmed.y),
+            ),
+            Misprediction::AngularVelocity {
+                predicted,
+                confirmed,
+            } => (predicted.0.to_string(), confirmed.0.to_string()),
+            Misprediction::ExternalForce {
+                predicted,
+                confirmed,
+            } => (
+                format!("{} {}", predicted.force().x, predicted.force().y),
+                format!("{} {}", confirmed.force().x, confirmed.force().y),
+            ),
+            Misprediction::ExternalImpulse {
+                predicted,
+                confirmed,
+            } => (
+                format!("{} {}", predicted.impulse().x, predicted.impulse().y),
+                format!("{} {}", confirmed.impulse().x, confirmed.impulse().y),
+            ),
+            Misprediction::ComputedMass {
+                predicted,
+                confirmed,
+            } => (predicted.value().to_string(), confirmed.value().to_string()),
+        }
+    }
+}
+
+/// A misprediction, with the tick of the confirmed value and the predicted entity.
+#[derive(Event, Clone, Copy, Debug)]
+pub struct RollbackRecord {
+    pub tick: Tick,
+    /// The predicted entity that was rolled back.
+    pub entity: Entity,
+    pub misprediction: Misprediction,
+}
+
+/// The last [`MAX_RECORDS`] rollbacks of the client, oldest first.
+#[derive(Resource, Default)]
+pub struct RollbackLog {
+    pub records: VecDeque<RollbackRecord>,
+}
+
+impl RollbackLog {
+    pub fn to_csv(&self) -> String {
+        let mut csv = String::from("tick,entity,component,predicted,confirmed,delta\n");
+
+        for record in &self.records {
+            let (predicted, confirmed) = record.misprediction.values();
+            let _ = writeln!(
+                csv,
+                "{},{},{},{},{},{}",
+                record.tick.0,
+                record.entity,
+                record.misprediction.component(),
+                predicted,
+                confirmed,
+                record.misprediction.delta(),
+            );
+        }
+
+        csv
+    }
+}
+
+fn collect_rollbacks(
+    mut log: ResMut<RollbackLog>,
+    mut diagnostics: Diagnostics,
+    mut records: EventReader<RollbackRecord>,
+) {
+    let mut rollbacks = [0; ROLLBACK_DIAGNOSTICS.len()];
+
+    for record in records.read() {
+        let misprediction = record.misprediction;
+        rollbacks[misprediction.diagnostic_index()] += 1;
+
+        debug!(
+            tick = ?record.tick,
+            entity = ?record.entity,
+            delta = misprediction.delta(),
+            "{} rollback: {misprediction:?}",
+            misprediction.component(),
+        );
+
+        if log.records.len() >= MAX_RECORDS {
+            log.records.pop_front();
+        }
+        log.records.push_back(*record);
+    }
+
+    for (path, count) in ROLLBACK_DIAGNOSTICS.iter().zip(rollbacks) {
+        diagnostics.add_measurement(path, || count as f64);
+    }
+}
+
+fn dump_rollbacks(keyboard: Res<ButtonInput<KeyCode>>, log: Res<RollbackLog>) {
+    if !keyboard.just_pressed(DUMP_KEY) {
+        return;
+    }
+
+    match fs::write(Path::new(CSV_PATH), log.to_csv()) {
+        Ok(()) => info!("Dumped {} rollbacks to {CSV_PATH}", log.records.len()),
+        Err(e) => error!("Failed to write {CSV_PATH}: {e}"),
+    }
+}
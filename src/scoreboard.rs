@@ -0,0 +1,123 @@
+//! Scoreboard listing all connected players, shown while Tab is held.
+
+use bevy::prelude::*;
+use lightyear::prelude::*;
+
+use crate::protocol::{Player, PlayerId, PlayerStats};
+
+const SCOREBOARD_KEY: KeyCode = KeyCode::Tab;
+
+/// Width of each scoreboard column, in pixels.
+const COLUMN_WIDTHS: [f32; 6] = [40., 140., 50., 50., 60., 60.];
+
+pub struct ScoreboardPlugin;
+
+impl Plugin for ScoreboardPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Startup, spawn_scoreboard)
+            .add_systems(Update, update_scoreboard);
+    }
+}
+
+#[derive(Component)]
+struct Scoreboard;
+
+fn spawn_scoreboard(mut commands: Commands) {
+    commands.spawn((
+        Name::new("Scoreboard"),
+        Scoreboard,
+        Node {
+            position_type: PositionType::Absolute,
+            top: Val::Px(40.),
+            left: Val::Px(100.),
+            flex_direction: FlexDirection::Column,
+            padding: UiRect::all(Val::Px(8.)),
+            ..default()
+        },
+        BackgroundColor(Color::srgba(0., 0., 0., 0.7)),
+        Visibility::Hidden,
+    ));
+}
+
+/// Show the scoreboard while its key is held, rebuilding the rows when it is opened and when
+/// the players or their stats change.
+fn update_scoreboard(
+    mut commands: Commands,
+    keyboard: Res<ButtonInput<KeyCode>>,
+    scoreboard: Single<(Entity, &mut Visibility), With<Scoreboard>>,
+    // every replicated player has exactly one confirmed entity on the client
+    player_q: Query<(&PlayerId, &Name, &PlayerStats), (With<Player>, With<Confirmed>)>,
+    changed_q: Query<
+        (),
+        (
+            With<Player>,
+            With<Confirmed>,
+            Or<(Changed<PlayerStats>, Changed<Name>)>,
+        ),
+    >,
+    mut removed_players: RemovedComponents<Player>,
+) {
+    let (scoreboard, mut visibility) = scoreboard.into_inner();
+    // read every frame so that players who left while it was hidden don't trigger a rebuild later
+    let players_left = removed_players.read().count() > 0;
+
+    if !keyboard.pressed(SCOREBOARD_KEY) {
+        visibility.set_if_neq(Visibility::Hidden);
+        return;
+    }
+    visibility.set_if_neq(Visibility::Inherited);
+
+    if !keyboard.just_pressed(SCOREBOARD_KEY) && !players_left && changed_q.is_empty() {
+        return;
+    }
+
+    let mut players: Vec<_> = player_q.iter().collect();
+    players.sort_by(|(_, _, a), (_, _, b)| b.score.cmp(&a.score));
+
+    commands
+        .entity(scoreboard)
+        .despawn_related::<Children>()
+        .with_children(|parent| {
+            spawn_row(
+                parent,
+                ["Id", "Name", "Kills", "Deaths", "Score", "Ping"].map(String::from),
+            );
+
+            for (id, name, stats) in players {
+                spawn_row(
+                    parent,
+                    [
+                        id.0.to_bits().to_string(),
+                        name.to_string(),
+                        stats.kills.to_string(),
+                        stats.deaths.to_string(),
+                        stats.score.to_string(),
+                        format!("{} ms", stats.ping),
+                    ],
+                );
+            }
+        });
+}
+
+fn spawn_row(parent: &mut ChildSpawnerCommands, cells: [String; 6]) {
+    parent
+        .spawn(Node {
+            flex_direction: FlexDirection::Row,
+            ..default()
+        })
+        .with_children(|row| {
+            for (cell, width) in cells.into_iter().zip(COLUMN_WIDTHS) {
+                row.spawn((
+                    Node {
+                        width: Val::Px(width),
+                        ..default()
+                    },
+                    Text::new(cell),
+                    TextFont {
+                        font_size: 14.,
+                        ..default()
+                    },
+                ));
+            }
+        });
+}
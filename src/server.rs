@@ -1,61 +1,204 @@
+use core::net::SocketAddr;
+
+use avian2d::prelude::Position;
 use bevy::prelude::*;
+#[cfg(not(target_family = "wasm"))]
+use lightyear::prelude::server::{ServerUdpIo, WebTransportServerIo};
 use lightyear::{
     netcode::NetcodeServer,
     prelude::{
-        server::{ClientOf, NetcodeConfig, ServerUdpIo, Start},
+        server::{ClientOf, NetcodeConfig, Server, Start},
         *,
     },
 };
 
 use crate::{
-    protocol::{Player, PlayerAction, PlayerId},
-    shared::{SERVER_ADDR, SERVER_REPLICATION_INTERVAL},
+    admin::AdminPlugin,
+    admission::{Admitted, RequestedName, ServerAdmissionPlugin},
+    chat::ServerChatPlugin,
+    cvars::{Cvars, PLAYER_SPEED, ServerCvarsPlugin},
+    level::{ServerLevelPlugin, SpawnPoint},
+    protocol::{Bullet, Player, PlayerAction, PlayerId, PlayerStats, Team},
+    shared::{LinkConditions, SERVER_ADDR},
+    transport::{MemoryServerLinks, ServerTransportOptions, Transport},
+};
+
+#[cfg(not(target_family = "wasm"))]
+use crate::transport::{
+    WEBSOCKET_SERVER_ADDR, WEBTRANSPORT_SERVER_ADDR, self_signed_identity, websocket_server_io,
 };
 
 pub struct MyServerPlugin;
 
 impl Plugin for MyServerPlugin {
     fn build(&self, app: &mut App) {
-        app.add_plugins(lightyear_avian2d::prelude::LagCompensationPlugin);
+        app.add_plugins((
+            lightyear_avian2d::prelude::LagCompensationPlugin,
+            ServerChatPlugin,
+            ServerAdmissionPlugin,
+            ServerLevelPlugin,
+            ServerCvarsPlugin,
+            AdminPlugin,
+        ));
+
+        app.init_resource::<Transport>()
+            .init_resource::<ServerTransportOptions>()
+            .init_resource::<MemoryServerLinks>();
 
         app.add_observer(handle_new_client)
-            .add_observer(handle_connected)
-            .add_systems(Startup, (startup, spawn_server_authoritative_entities))
-            .add_systems(FixedUpdate, handle_player_movement);
+            .add_observer(spawn_admitted_player)
+            .add_systems(Startup, startup)
+            .add_systems(
+                Update,
+                (
+                    attach_memory_links.run_if(resource_equals(Transport::Memory)),
+                    update_player_ping,
+                ),
+            )
+            .add_systems(FixedUpdate, (handle_player_movement, handle_bullet_hits));
+    }
+}
+
+/// Longest display name accepted by the server.
+pub(crate) const MAX_NAME_LEN: usize = 16;
+
+const DEFAULT_PLAYER_NAME: &str = "Player";
+
+/// Distance between a bullet and a player at which the player is hit.
+const BULLET_HIT_RADIUS: f32 = 37.;
+
+/// Score given to the shooter for each kill.
+const SCORE_PER_KILL: u32 = 10;
+
+/// Start one server per transport, as a server has a single IO.
+fn startup(
+    mut commands: Commands,
+    transport: Res<Transport>,
+    transport_options: Res<ServerTransportOptions>,
+) -> Result {
+    if *transport == Transport::Memory {
+        // links are attached by `attach_memory_links`
+        spawn_server(&mut commands, "Server", SERVER_ADDR, ());
+        return Ok(());
+    }
+
+    #[cfg(not(target_family = "wasm"))]
+    {
+        spawn_server(
+            &mut commands,
+            "UDP server",
+            SERVER_ADDR,
+            ServerUdpIo::default(),
+        );
+        if transport_options.webtransport {
+            let io = WebTransportServerIo {
+                certificate: self_signed_identity()?,
+            };
+            spawn_server(
+                &mut commands,
+                "WebTransport server",
+                WEBTRANSPORT_SERVER_ADDR,
+                io,
+            );
+        }
+        if transport_options.websocket {
+            spawn_server(
+                &mut commands,
+                "WebSocket server",
+                WEBSOCKET_SERVER_ADDR,
+                websocket_server_io(),
+            );
+        }
+    }
+
+    #[cfg(target_family = "wasm")]
+    {
+        let _ = transport_options;
+        error!("Browsers can't open server sockets, the server only accepts in-memory links");
     }
+
+    Ok(())
 }
 
-/// Start the server
-fn startup(mut commands: Commands) -> Result {
+fn spawn_server(commands: &mut Commands, name: &'static str, addr: SocketAddr, io: impl Bundle) {
     let server = commands
         .spawn((
-            Name::new("Server"),
+            Name::new(name),
             NetcodeServer::new(NetcodeConfig::default()),
-            LocalAddr(SERVER_ADDR),
-            ServerUdpIo::default(),
+            LocalAddr(addr),
+            io,
         ))
         .id();
-
     commands.trigger_targets(Start, server);
+}
 
-    Ok(())
+/// Attach the in-memory links of clients running in the same process to the server.
+fn attach_memory_links(
+    mut commands: Commands,
+    mut memory_links: ResMut<MemoryServerLinks>,
+    server: Single<Entity, With<Server>>,
+) {
+    for (client_addr, io) in memory_links.0.drain(..) {
+        commands.spawn((
+            LinkOf { server: *server },
+            Link::new(None),
+            PeerAddr(client_addr),
+            io,
+        ));
+    }
 }
 
-fn spawn_server_authoritative_entities(mut commands: Commands) {
-    // Spawn server-authoritative ball
-    commands.spawn((
-        crate::protocol::Ball,
-        crate::protocol::Ball::get_physics_bundle(),
-        Name::from("Ball"),
-        Replicate::to_clients(NetworkTarget::All),
-        PredictionTarget::to_clients(NetworkTarget::All),
-    ));
+/// Spawn a server-authoritative ball, predicted by every client.
+pub fn spawn_ball(commands: &mut Commands, position: Vec2) -> Entity {
+    commands
+        .spawn((
+            crate::protocol::Ball,
+            crate::protocol::Ball::get_physics_bundle(),
+            Name::from("Ball"),
+            Replicate::to_clients(NetworkTarget::All),
+            PredictionTarget::to_clients(NetworkTarget::All),
+        ))
+        // the physics bundle already contains a default `Position`
+        .insert(Position(position))
+        .id()
 }
 
-pub(crate) fn handle_new_client(trigger: Trigger<OnAdd, LinkOf>, mut commands: Commands) {
+/// Spawn a player controlled by no client, interpolated by every client.
+pub fn spawn_bot(commands: &mut Commands, asset_server: &AssetServer, position: Vec2) -> Entity {
+    commands
+        .spawn((
+            Name::new("Bot"),
+            Player,
+            Player::get_physics_bundle(),
+            Position(position),
+            PlayerId(PeerId::Server),
+            Team::from_peer(PeerId::Server),
+            PlayerStats::default(),
+            Sprite {
+                image: asset_server.load("art/ball.png"),
+                ..default()
+            },
+            Replicate::to_clients(NetworkTarget::All),
+            InterpolationTarget::to_clients(NetworkTarget::All),
+        ))
+        .id()
+}
+
+pub(crate) fn handle_new_client(
+    trigger: Trigger<OnAdd, LinkOf>,
+    mut link_q: Query<&mut Link>,
+    link_conditions: Res<LinkConditions>,
+    cvars: Res<Cvars>,
+    mut commands: Commands,
+) {
+    // links of clients are spawned by `ServerUdpIo`, so simulated conditions are applied afterwards
+    if let Ok(mut link) = link_q.get_mut(trigger.target()) {
+        link.recv.conditioner = link_conditions.recv_conditioner();
+    }
+
     commands.entity(trigger.target()).insert((
         ReplicationSender::new(
-            SERVER_REPLICATION_INTERVAL,
+            cvars.replication_interval(),
             SendUpdatesMode::SinceLastAck,
             false,
         ),
@@ -63,25 +206,46 @@ pub(crate) fn handle_new_client(trigger: Trigger<OnAdd, LinkOf>, mut commands: C
     ));
 }
 
-pub(crate) fn handle_connected(
-    trigger: Trigger<OnAdd, Connected>,
-    client_q: Query<&RemoteId, With<ClientOf>>,
+/// Spawn the player of a client once it is admitted by the [`ServerAdmissionPlugin`].
+///
+/// The player is named as requested in the `ClientHello` of the client, with invalid characters
+/// stripped and a numeric suffix when the name is already used by another player.
+pub(crate) fn spawn_admitted_player(
+    trigger: Trigger<OnAdd, Admitted>,
+    client_q: Query<(&RemoteId, &RequestedName), With<ClientOf>>,
+    spawn_point_q: Query<&GlobalTransform, With<SpawnPoint>>,
+    player_q: Query<&Name, (With<Player>, With<Replicate>)>,
     mut commands: Commands,
     asset_server: Res<AssetServer>,
 ) {
     // Fin id of connected client
-    let Ok(client_id) = client_q.get(trigger.target()) else {
+    let Ok((client_id, requested_name)) = client_q.get(trigger.target()) else {
         return;
     };
 
     let client_id = client_id.0;
+    let taken: Vec<String> = player_q.iter().map(|name| name.to_string()).collect();
+    let name = deduplicate_name(sanitize_name(&requested_name.0), &taken);
+
+    // players spawn at the spawn points of the level in turn
+    let spawn_points: Vec<Vec2> = spawn_point_q
+        .iter()
+        .map(|transform| transform.translation().truncate())
+        .collect();
+    let position = match spawn_points.len() {
+        0 => Vec2::ZERO,
+        len => spawn_points[taken.len() % len],
+    };
 
     let entity = commands
         .spawn((
-            Name::new("Player"),
+            Name::new(name),
             Player,
             Player::get_physics_bundle(),
+            Position(position),
             PlayerId(client_id),
+            Team::from_peer(client_id),
+            PlayerStats::default(),
             Sprite {
                 image: asset_server.load("art/ball.png"),
                 ..default()
@@ -106,14 +270,106 @@ pub(crate) fn handle_connected(
     );
 }
 
+pub(crate) fn sanitize_name(requested: &str) -> String {
+    let name: String = requested
+        .chars()
+        .filter(|c| c.is_alphanumeric() || matches!(c, ' ' | '_' | '-'))
+        .take(MAX_NAME_LEN)
+        .collect();
+
+    match name.trim() {
+        "" => DEFAULT_PLAYER_NAME.to_string(),
+        name => name.to_string(),
+    }
+}
+
+/// Add a number to `name` if it is taken, shortening it to keep within [`MAX_NAME_LEN`].
+pub(crate) fn deduplicate_name(name: String, taken: &[String]) -> String {
+    if !taken.contains(&name) {
+        return name;
+    }
+
+    (2..)
+        .map(|i| {
+            let suffix = format!(" ({i})");
+            let base: String = name
+                .chars()
+                .take(MAX_NAME_LEN.saturating_sub(suffix.len()))
+                .collect();
+            format!("{}{suffix}", base.trim_end())
+        })
+        .find(|candidate| !taken.contains(candidate))
+        .unwrap()
+}
+
 /// Read client inputs and move players in server therefore giving a basis for other clients
 pub fn handle_player_movement(
     mut position_query: Query<(
         &mut avian2d::prelude::LinearVelocity,
         &leafwing_input_manager::prelude::ActionState<PlayerAction>,
     )>,
+    cvars: Res<Cvars>,
 ) {
+    let speed = cvars.get(&PLAYER_SPEED);
     for (mut velocity, inputs) in position_query.iter_mut() {
-        crate::shared::move_player(&mut velocity, inputs);
+        crate::shared::move_player(&mut velocity, inputs, speed);
+    }
+}
+
+/// Keep the ping displayed in the scoreboard up to date.
+fn update_player_ping(
+    link_q: Query<&Link, With<ClientOf>>,
+    mut player_q: Query<(&ControlledBy, &mut PlayerStats), With<Player>>,
+) {
+    for (controlled_by, mut stats) in player_q.iter_mut() {
+        let Ok(link) = link_q.get(controlled_by.owner) else {
+            continue;
+        };
+
+        let ping = link.stats.rtt.as_millis() as u32;
+        // avoid triggering replication when nothing changed
+        if stats.ping != ping {
+            stats.ping = ping;
+        }
+    }
+}
+
+/// Scoring rule: a bullet reaching a player of another team kills that player. The victim gets a
+/// death, the shooter a kill and [`SCORE_PER_KILL`], and the bullet is spent. Bullets pass
+/// through teammates.
+fn handle_bullet_hits(
+    mut commands: Commands,
+    bullet_q: Query<(Entity, &PlayerId, &Position), (With<Bullet>, With<Replicate>)>,
+    mut player_q: Query<(&PlayerId, &Team, &Position, &mut PlayerStats), With<Player>>,
+) {
+    for (bullet, shooter, bullet_position) in bullet_q.iter() {
+        // bullets of players who left have no team, and can't score
+        let Some(shooter_team) = player_q
+            .iter()
+            .find(|(id, ..)| *id == shooter)
+            .map(|(_, team, ..)| *team)
+        else {
+            continue;
+        };
+        let Some(victim) = player_q
+            .iter()
+            .find(|(_, team, position, _)| {
+                **team != shooter_team && position.distance(bullet_position.0) < BULLET_HIT_RADIUS
+            })
+            .map(|(id, ..)| *id)
+        else {
+            continue;
+        };
+
+        for (id, _, _, mut stats) in player_q.iter_mut() {
+            if *id == victim {
+                stats.deaths += 1;
+            } else if id == shooter {
+                stats.kills += 1;
+                stats.score += SCORE_PER_KILL;
+            }
+        }
+
+        commands.entity(bullet).despawn();
     }
 }
@@ -10,11 +10,11 @@ use core::net::{IpAddr, Ipv4Addr, SocketAddr};
 use core::time::Duration;
 use lightyear::prelude::*;
 
-use crate::protocol::{Bullet, Player, PlayerAction, PlayerId, Wall};
-
-pub const FIXED_TIMESTEP_HZ: f64 = 64.0;
-
-pub const SERVER_REPLICATION_INTERVAL: Duration = Duration::from_millis(100);
+use crate::{
+    cvars::{BULLET_SPEED, Cvars, CvarsPlugin},
+    level::LevelPlugin,
+    protocol::{Bullet, Player, PlayerAction, PlayerId},
+};
 
 pub const SERVER_ADDR: SocketAddr = SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), 5000);
 
@@ -23,7 +23,14 @@ pub struct SharedPlugin;
 
 impl Plugin for SharedPlugin {
     fn build(&self, app: &mut App) {
-        app.add_systems(Startup, prepare_level)
+        app.register_type::<LinkConditions>()
+            .init_resource::<LinkConditions>()
+            .add_systems(
+                Update,
+                apply_link_conditions.run_if(resource_changed::<LinkConditions>),
+            );
+
+        app.add_plugins((CvarsPlugin, LevelPlugin))
             .add_systems(FixedUpdate, shoot);
 
         app.add_systems(
@@ -37,38 +44,45 @@ impl Plugin for SharedPlugin {
     }
 }
 
-fn prepare_level(mut commands: Commands) {
-    commands.spawn((
-        Wall,
-        Collider::rectangle(20., 450.),
-        Transform::from_xyz(-250., 0., 0.),
-    ));
-
-    commands.spawn((
-        Wall,
-        Collider::rectangle(20., 450.),
-        Transform::from_xyz(250., 0., 0.),
-    ));
-
-    commands.spawn((
-        Wall,
-        Collider::rectangle(600., 20.),
-        Transform::from_xyz(0., 200., 0.),
-    ));
-
-    commands.spawn((
-        Wall,
-        Collider::rectangle(600., 20.),
-        Transform::from_xyz(0., -200., 0.),
-    ));
-
-    commands.spawn((Wall, Transform::from_xyz(0., 100., 0.)));
+/// Simulated latency, jitter and packet loss applied to every received packet.
+///
+/// Set from the `--sim-*` CLI flags and can be adjusted live from the editor.
+#[derive(Resource, Reflect, Debug, Default, Clone, Copy, PartialEq)]
+#[reflect(Resource)]
+pub struct LinkConditions {
+    pub latency_ms: u64,
+    pub jitter_ms: u64,
+    /// Ratio of dropped packets, between 0 and 1.
+    pub loss: f32,
+}
+
+impl LinkConditions {
+    /// Conditioner to give to a [`Link`], `None` if the network is not degraded.
+    pub fn recv_conditioner(&self) -> Option<RecvLinkConditioner> {
+        if *self == Self::default() {
+            return None;
+        }
+
+        Some(RecvLinkConditioner::new(LinkConditionerConfig {
+            incoming_latency: Duration::from_millis(self.latency_ms),
+            incoming_jitter: Duration::from_millis(self.jitter_ms),
+            incoming_loss: self.loss,
+        }))
+    }
+}
+
+/// Apply the new conditions to all existing links.
+fn apply_link_conditions(conditions: Res<LinkConditions>, mut link_q: Query<&mut Link>) {
+    for mut link in link_q.iter_mut() {
+        link.recv.conditioner = conditions.recv_conditioner();
+    }
 }
 
 // TODO: make system
 pub fn move_player(
     velocity: &mut avian2d::prelude::LinearVelocity,
     action_state: &leafwing_input_manager::prelude::ActionState<PlayerAction>,
+    speed: f32,
 ) {
     let mut direction = Vec2::ZERO;
 
@@ -85,7 +99,7 @@ pub fn move_player(
         direction.x = -1.;
     }
 
-    direction = direction.normalize_or_zero() * 150.;
+    direction = direction.normalize_or_zero() * speed;
 
     *velocity = LinearVelocity(direction);
 }
@@ -106,6 +120,7 @@ fn shoot(
         (Or<(With<Predicted>, With<Replicate>)>, With<Player>),
     >,
     asset_server: Res<AssetServer>,
+    cvars: Res<Cvars>,
 ) {
     for (player_id, player_transform, action_state, controlled_by) in player_q.iter() {
         if action_state.just_pressed(&PlayerAction::Shoot) {
@@ -123,7 +138,7 @@ fn shoot(
                     ..default()
                 },
                 RigidBody::Kinematic,
-                LinearVelocity(Vec2::new(20., 0.)),
+                LinearVelocity(Vec2::new(cvars.get(&BULLET_SPEED), 0.)),
                 Transform {
                     translation: player_transform.translation,
                     scale: Vec3::splat(0.1),
@@ -0,0 +1,147 @@
+use core::net::{IpAddr, Ipv4Addr};
+
+use bevy::prelude::*;
+use lightyear::prelude::*;
+
+use super::harness::Stepper;
+use crate::{
+    admin::{RconPassword, password_matches, run_command},
+    admission::ConnectionAttempts,
+    level::CurrentLevel,
+    protocol::{Ball, ControlChannel, RemoteCommand, RollbackTolerances},
+};
+
+#[test]
+fn list_shows_connected_players() {
+    let mut stepper = Stepper::new(2);
+    stepper.wait_for_players();
+    // let the names sent by the clients reach the server
+    stepper.frame_step_n(10);
+
+    let output = run_command(stepper.server_world(), "list").unwrap();
+    assert!(output.contains("Test client 0"), "{output}");
+    assert!(output.contains("Test client 1"), "{output}");
+}
+
+#[test]
+fn kick_disconnects_the_client() {
+    let mut stepper = Stepper::new(1);
+    stepper.wait_for_players();
+
+    run_command(stepper.server_world(), "kick 0 spamming").unwrap();
+
+    let client = stepper.clients[0];
+    stepper.wait_until("the client to be disconnected", |stepper| {
+        stepper
+            .client_world(0)
+            .get::<Disconnected>(client)
+            .is_some()
+    });
+}
+
+#[test]
+fn wrong_rcon_passwords_kick_the_client() {
+    let mut stepper = Stepper::new(1);
+    stepper
+        .server_world()
+        .insert_resource(RconPassword(Some("secret".to_string())));
+    stepper.wait_for_players();
+
+    let client = stepper.clients[0];
+    for _ in 0..3 {
+        stepper
+            .client_world(0)
+            .get_mut::<MessageSender<RemoteCommand>>(client)
+            .unwrap()
+            .send::<ControlChannel>(RemoteCommand {
+                password: "guess".to_string(),
+                command: "list".to_string(),
+            });
+    }
+
+    stepper.wait_until("the client to be disconnected", |stepper| {
+        stepper
+            .client_world(0)
+            .get::<Disconnected>(client)
+            .is_some()
+    });
+
+    // kept for the IP address, a reconnecting client can't guess more
+    let failures = stepper
+        .server_world()
+        .resource::<ConnectionAttempts>()
+        .rcon_failures
+        .get(&IpAddr::V4(Ipv4Addr::LOCALHOST))
+        .copied();
+    assert_eq!(failures, Some(3));
+}
+
+#[test]
+fn rcon_password_comparison() {
+    assert!(password_matches("secret", "secret"));
+    assert!(!password_matches("secret", "secreT"));
+    assert!(!password_matches("secret", "secret!"));
+    assert!(!password_matches("secret", ""));
+}
+
+#[test]
+fn changelevel_is_replicated_to_clients() {
+    let mut stepper = Stepper::new(1);
+    stepper.wait_for_players();
+
+    run_command(stepper.server_world(), "changelevel pillars").unwrap();
+    assert!(run_command(stepper.server_world(), "changelevel nowhere").is_err());
+
+    stepper.wait_until("the client to load the level", |stepper| {
+        stepper.client_world(0).resource::<CurrentLevel>().name == "pillars"
+    });
+}
+
+#[test]
+fn spawned_ball_is_replicated() {
+    let mut stepper = Stepper::new(1);
+    stepper.wait_for_players();
+
+    let count_balls = |world: &mut World| {
+        world
+            .query_filtered::<(), (With<Ball>, With<Confirmed>)>()
+            .iter(world)
+            .count()
+    };
+    stepper.wait_until("the first ball to be replicated", |stepper| {
+        count_balls(stepper.client_world(0)) == 1
+    });
+
+    run_command(stepper.server_world(), "spawn ball").unwrap();
+
+    stepper.wait_until("the new ball to be replicated", |stepper| {
+        count_balls(stepper.client_world(0)) == 2
+    });
+}
+
+#[test]
+fn set_changes_a_resource_field() {
+    let mut stepper = Stepper::new(0);
+
+    run_command(
+        stepper.server_world(),
+        "set RollbackTolerances.position 0.5",
+    )
+    .unwrap();
+    assert_eq!(
+        stepper
+            .server_world()
+            .resource::<RollbackTolerances>()
+            .position,
+        0.5
+    );
+
+    assert!(
+        run_command(
+            stepper.server_world(),
+            "set RollbackTolerances.position abc"
+        )
+        .is_err()
+    );
+    assert!(run_command(stepper.server_world(), "set Nothing.field 1").is_err());
+}
@@ -0,0 +1,106 @@
+use bevy::prelude::*;
+use lightyear::prelude::*;
+
+use super::harness::Stepper;
+use crate::{
+    admission::{AdmissionSettings, BanList, WhenFull},
+    protocol::{Player, ProtocolHash},
+};
+
+fn is_disconnected(stepper: &mut Stepper, client: usize) -> bool {
+    let entity = stepper.clients[client];
+    stepper
+        .client_world(client)
+        .get::<Disconnected>(entity)
+        .is_some()
+}
+
+fn server_player_count(stepper: &mut Stepper) -> usize {
+    let world = stepper.server_world();
+    world
+        .query_filtered::<(), (With<Player>, With<Replicate>)>()
+        .iter(world)
+        .count()
+}
+
+/// Server accepting a single player, with a client connecting first.
+fn full_server(when_full: WhenFull) -> Stepper {
+    let mut stepper = Stepper::new(2);
+    stepper.server_world().insert_resource(AdmissionSettings {
+        max_players: 1,
+        when_full,
+        ..default()
+    });
+    stepper.wait_until("a player to be spawned", |stepper| {
+        server_player_count(stepper) == 1
+    });
+    stepper
+}
+
+#[test]
+fn client_with_another_protocol_is_refused_before_playing() {
+    let mut stepper = Stepper::new(1);
+    stepper.client_world(0).insert_resource(ProtocolHash(0));
+
+    stepper.wait_until("the client to be disconnected", |stepper| {
+        is_disconnected(stepper, 0)
+    });
+    assert_eq!(server_player_count(&mut stepper), 0);
+}
+
+#[test]
+fn client_is_refused_when_the_server_is_full() {
+    let mut stepper = full_server(WhenFull::Reject);
+
+    stepper.wait_until("a client to be disconnected", |stepper| {
+        is_disconnected(stepper, 0) != is_disconnected(stepper, 1)
+    });
+    assert_eq!(server_player_count(&mut stepper), 1);
+}
+
+#[test]
+fn clients_greeting_together_do_not_exceed_max_players() {
+    let mut stepper = Stepper::new(3);
+    stepper.server_world().insert_resource(AdmissionSettings {
+        max_players: 2,
+        when_full: WhenFull::Reject,
+        ..default()
+    });
+
+    stepper.wait_until("a client to be disconnected", |stepper| {
+        (0..3).any(|client| is_disconnected(stepper, client))
+    });
+    stepper.frame_step_n(10);
+    assert_eq!(server_player_count(&mut stepper), 2);
+}
+
+#[test]
+fn queued_client_joins_when_a_player_leaves() {
+    let mut stepper = full_server(WhenFull::Queue);
+    stepper.frame_step_n(10);
+    assert_eq!(server_player_count(&mut stepper), 1);
+
+    let playing = (0..2)
+        .find(|&client| stepper.controlled_player(client).is_some())
+        .expect("one client should be playing");
+    let queued = 1 - playing;
+    assert!(!is_disconnected(&mut stepper, queued));
+
+    let entity = stepper.clients[playing];
+    stepper
+        .client_world(playing)
+        .trigger_targets(Disconnect, entity);
+
+    stepper.wait_until("the queued client to play", |stepper| {
+        stepper.controlled_player(queued).is_some()
+    });
+}
+
+#[test]
+fn ban_list_ignores_comments_and_invalid_lines() {
+    let ban_list = BanList::parse("# griefers\n10.0.0.1\n\n::1 # local\nnot an ip\n");
+
+    assert_eq!(ban_list.0.len(), 2);
+    assert!(ban_list.0.contains(&"10.0.0.1".parse().unwrap()));
+    assert!(ban_list.0.contains(&"::1".parse().unwrap()));
+}
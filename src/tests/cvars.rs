@@ -0,0 +1,78 @@
+use std::collections::HashMap;
+
+use super::harness::Stepper;
+use crate::{
+    admin::run_command,
+    cvars::{ConfigValue, Cvars, PLAYER_SPEED, REPLICATION_INTERVAL_MS, TICK_RATE, parse_cvar_arg},
+};
+
+#[test]
+fn cvars_are_parsed_with_their_type() {
+    let mut cvars = Cvars::default();
+    assert_eq!(cvars.get(&PLAYER_SPEED), PLAYER_SPEED.default);
+
+    cvars.set_str("player_speed", "200").unwrap();
+    assert_eq!(cvars.get(&PLAYER_SPEED), 200.);
+
+    assert!(cvars.set_str("replication_interval_ms", "1.5").is_err());
+    assert_eq!(
+        cvars.get(&REPLICATION_INTERVAL_MS),
+        REPLICATION_INTERVAL_MS.default
+    );
+    assert!(cvars.set_str("unknown", "1").is_err());
+
+    assert_eq!(
+        parse_cvar_arg("player_speed = 200"),
+        Ok((String::from("player_speed"), String::from("200")))
+    );
+    assert!(parse_cvar_arg("player_speed").is_err());
+}
+
+#[test]
+fn config_file_values_are_typed() {
+    let file: HashMap<String, ConfigValue> = ron::from_str(
+        r#"{"player_speed": 200, "tick_rate": 30.5, "replication_interval_ms": "50"}"#,
+    )
+    .unwrap();
+
+    let mut cvars = Cvars::default();
+    let mut errors = Vec::new();
+    for (name, value) in file {
+        if cvars.set_config(&name, value).is_err() {
+            errors.push(name);
+        }
+    }
+
+    assert_eq!(cvars.get(&PLAYER_SPEED), 200.);
+    assert_eq!(cvars.get(&TICK_RATE), 30.5);
+    assert_eq!(errors, ["replication_interval_ms"]);
+    assert_eq!(
+        cvars.get(&REPLICATION_INTERVAL_MS),
+        REPLICATION_INTERVAL_MS.default
+    );
+}
+
+#[test]
+fn replicated_cvars_are_sent_to_clients() {
+    let mut stepper = Stepper::new(1);
+    stepper.wait_for_players();
+
+    run_command(stepper.server_world(), "set player_speed 300").unwrap();
+    run_command(stepper.server_world(), "set replication_interval_ms 50").unwrap();
+
+    stepper.wait_until("the client to receive the cvar", |stepper| {
+        stepper
+            .client_world(0)
+            .resource::<Cvars>()
+            .get(&PLAYER_SPEED)
+            == 300.
+    });
+    // only gameplay cvars are replicated
+    assert_eq!(
+        stepper
+            .client_world(0)
+            .resource::<Cvars>()
+            .get(&REPLICATION_INTERVAL_MS),
+        REPLICATION_INTERVAL_MS.default
+    );
+}
@@ -0,0 +1,114 @@
+use core::{net::SocketAddr, time::Duration};
+use std::net::Ipv4Addr;
+
+use bevy::prelude::*;
+use lightyear::prelude::*;
+
+use super::harness::Stepper;
+use crate::{
+    discovery::{
+        BeaconListener, BeaconSender, JoinButton, ServerBeacon, ServerBrowser, add_server_browser,
+    },
+    protocol::{CliClientOptions, ProtocolHash},
+    shared::SERVER_ADDR,
+    transport::Transport,
+};
+
+fn beacon() -> ServerBeacon {
+    ServerBeacon {
+        name: String::from("Test server"),
+        map: String::from("arena"),
+        players: 3,
+        protocol_hash: 0x1234,
+        transport: Transport::Udp,
+        port: 5000,
+    }
+}
+
+#[test]
+fn beacon_roundtrip() {
+    let beacon = beacon();
+    assert_eq!(ServerBeacon::decode(&beacon.encode()), Some(beacon));
+    assert_eq!(ServerBeacon::decode(b"not a beacon"), None);
+}
+
+#[test]
+fn beacon_is_received_on_loopback() {
+    // port 0 so that tests don't conflict with a running client
+    let listener = BeaconListener::bind(SocketAddr::from((Ipv4Addr::LOCALHOST, 0))).unwrap();
+    let sender = BeaconSender::new(vec![listener.local_addr().unwrap()]).unwrap();
+
+    sender.send(&beacon());
+
+    let mut received = Vec::new();
+    for _ in 0..100 {
+        received.extend(listener.receive());
+        if !received.is_empty() {
+            break;
+        }
+        std::thread::sleep(Duration::from_millis(10));
+    }
+
+    assert_eq!(
+        received,
+        vec![(SocketAddr::from((Ipv4Addr::LOCALHOST, 5000)), beacon())]
+    );
+}
+
+#[test]
+fn pressing_a_server_joins_it() {
+    let mut stepper = Stepper::new(1);
+    // join from the browser instead of the client spawned by the harness
+    let client_entity = stepper.clients[0];
+    let options = stepper
+        .client_world(0)
+        .entity_mut(client_entity)
+        .take::<CliClientOptions>()
+        .unwrap();
+    stepper.client_world(0).despawn(client_entity);
+
+    let listener = BeaconListener::bind(SocketAddr::from((Ipv4Addr::LOCALHOST, 0))).unwrap();
+    let sender = BeaconSender::new(vec![listener.local_addr().unwrap()]).unwrap();
+    add_server_browser(&mut stepper.client_apps[0], listener);
+    stepper
+        .client_world(0)
+        .insert_resource(ServerBrowser { options });
+    let server_beacon = ServerBeacon {
+        protocol_hash: stepper.client_world(0).resource::<ProtocolHash>().0,
+        transport: Transport::Memory,
+        port: SERVER_ADDR.port(),
+        ..beacon()
+    };
+
+    let mut button = None;
+    stepper.wait_until("the server to be listed", |stepper| {
+        sender.send(&server_beacon);
+        let world = stepper.client_world(0);
+        button = world
+            .query_filtered::<Entity, With<JoinButton>>()
+            .iter(world)
+            .next();
+        button.is_some()
+    });
+    let button = button.unwrap();
+
+    // the same beacon keeps being received, the entry must stay pressable
+    for _ in 0..5 {
+        sender.send(&server_beacon);
+        std::thread::sleep(Duration::from_millis(10));
+        stepper.frame_step();
+    }
+    stepper
+        .client_world(0)
+        .entity_mut(button)
+        .insert(Interaction::Pressed);
+
+    stepper.wait_until("the client to connect", |stepper| {
+        let world = stepper.client_world(0);
+        world
+            .query_filtered::<(), (With<Client>, With<Connected>)>()
+            .iter(world)
+            .next()
+            .is_some()
+    });
+}
@@ -0,0 +1,98 @@
+use bevy::prelude::*;
+
+use crate::{
+    edit_history::{EditHistory, InspectorSnapshot},
+    protocol::RollbackTolerances,
+};
+
+fn world() -> World {
+    let mut world = World::new();
+    world.init_resource::<AppTypeRegistry>();
+    {
+        let registry = world.resource::<AppTypeRegistry>();
+        let mut registry = registry.write();
+        registry.register::<RollbackTolerances>();
+        registry.register::<Transform>();
+    }
+    world.insert_resource(RollbackTolerances::default());
+    world
+}
+
+/// Run `edit` like the inspector shown during an edit.
+fn inspect(world: &mut World, history: &mut EditHistory, edit: impl FnOnce(&mut World)) {
+    let last_run = world.increment_change_tick();
+    edit(world);
+    history.inspector_shown(world, last_run);
+}
+
+fn begin_resource_edit(world: &World, history: &mut EditHistory) {
+    let registry = world.resource::<AppTypeRegistry>().clone();
+    history.begin_inspector_edit(InspectorSnapshot::resource(
+        world,
+        &registry.read(),
+        core::any::TypeId::of::<RollbackTolerances>(),
+    ));
+}
+
+#[test]
+fn resource_edit_is_undone_and_redone() {
+    let mut world = world();
+    let mut history = EditHistory::default();
+    let original = *world.resource::<RollbackTolerances>();
+
+    // dragging a value changes it on several frames, which makes a single edit
+    begin_resource_edit(&world, &mut history);
+    inspect(&mut world, &mut history, |world| {
+        world.resource_mut::<RollbackTolerances>().position = 0.5;
+    });
+    inspect(&mut world, &mut history, |world| {
+        world.resource_mut::<RollbackTolerances>().position = 0.7;
+    });
+    history.end_inspector_edit();
+
+    history.undo(&mut world);
+    assert_eq!(*world.resource::<RollbackTolerances>(), original);
+
+    history.redo(&mut world);
+    assert_eq!(world.resource::<RollbackTolerances>().position, 0.7);
+}
+
+#[test]
+fn changes_made_outside_the_inspector_are_not_recorded() {
+    let mut world = world();
+    let mut history = EditHistory::default();
+
+    begin_resource_edit(&world, &mut history);
+    world.resource_mut::<RollbackTolerances>().position = 0.5;
+    inspect(&mut world, &mut history, |_| {});
+    history.end_inspector_edit();
+
+    // nothing to undo, the change was not made in the inspector
+    assert!(!history.can_undo());
+    history.undo(&mut world);
+    assert_eq!(world.resource::<RollbackTolerances>().position, 0.5);
+}
+
+#[test]
+fn component_edit_is_undone() {
+    let mut world = world();
+    let mut history = EditHistory::default();
+    let entity = world.spawn(Transform::from_xyz(1., 2., 0.)).id();
+
+    let registry = world.resource::<AppTypeRegistry>().clone();
+    history.begin_inspector_edit(InspectorSnapshot::entities(
+        &world,
+        &registry.read(),
+        &[entity],
+    ));
+    inspect(&mut world, &mut history, |world| {
+        world.get_mut::<Transform>(entity).unwrap().translation.x = 10.;
+    });
+    history.end_inspector_edit();
+
+    history.undo(&mut world);
+    assert_eq!(
+        world.get::<Transform>(entity).unwrap().translation,
+        Vec3::new(1., 2., 0.)
+    );
+}
@@ -0,0 +1,172 @@
+//! Deterministic test harness running a server [`App`] and several client [`App`]s in one process.
+//!
+//! Apps use the in-memory [`Transport`] instead of UDP sockets
+//! and time is advanced manually, one fixed tick per frame.
+
+use core::{
+    net::{IpAddr, Ipv4Addr, SocketAddr},
+    time::Duration,
+};
+
+use avian2d::prelude::{Gravity, PhysicsPlugins};
+use bevy::{input::InputPlugin, prelude::*, state::app::StatesPlugin, time::TimeUpdateStrategy};
+use lightyear::prelude::{client::ClientPlugins, server::ServerPlugins, *};
+
+use crate::{
+    client::MyClientPlugin,
+    cvars::Cvars,
+    protocol::{CliClientOptions, Player, ProtocolPlugin},
+    server::MyServerPlugin,
+    shared::{SERVER_ADDR, SharedPlugin},
+    transport::{MemoryServerLinks, Transport, memory_link},
+};
+
+/// Maximum number of frames to wait for a condition before failing the test.
+const MAX_WAIT_FRAMES: usize = 500;
+
+pub struct Stepper {
+    pub server_app: App,
+    pub client_apps: Vec<App>,
+    /// Client entity in each client app.
+    pub clients: Vec<Entity>,
+}
+
+impl Stepper {
+    /// Create a server and `num_clients` clients, and start connecting them.
+    pub fn new(num_clients: usize) -> Self {
+        let tick_duration = Cvars::default().tick_duration();
+
+        let mut server_app = base_app(tick_duration);
+        server_app
+            .add_plugins(ServerPlugins { tick_duration })
+            .insert_resource(Transport::Memory);
+        add_gameplay_plugins(&mut server_app);
+        server_app.add_plugins(MyServerPlugin);
+
+        let mut client_apps = Vec::with_capacity(num_clients);
+        let mut clients = Vec::with_capacity(num_clients);
+
+        for client_id in 0..num_clients as u64 {
+            let client_addr =
+                SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), 1000 + client_id as u16);
+            let (server_link, client_link) = memory_link(client_addr);
+
+            server_app
+                .world_mut()
+                .resource_mut::<MemoryServerLinks>()
+                .0
+                .push(server_link);
+
+            let mut client_app = base_app(tick_duration);
+            client_app
+                .add_plugins(ClientPlugins { tick_duration })
+                .insert_resource(Transport::Memory)
+                .insert_resource(client_link);
+            add_gameplay_plugins(&mut client_app);
+            client_app.add_plugins(MyClientPlugin);
+
+            let client = client_app
+                .world_mut()
+                .spawn(CliClientOptions {
+                    id: client_id,
+                    name: format!("Test client {client_id}"),
+                    server_addr: SERVER_ADDR,
+                    certificate_digest: String::new(),
+                })
+                .id();
+
+            client_apps.push(client_app);
+            clients.push(client);
+        }
+
+        Self {
+            server_app,
+            client_apps,
+            clients,
+        }
+    }
+
+    pub fn server_world(&mut self) -> &mut World {
+        self.server_app.world_mut()
+    }
+
+    pub fn client_world(&mut self, client: usize) -> &mut World {
+        self.client_apps[client].world_mut()
+    }
+
+    /// Run one frame on the server, then on every client.
+    pub fn frame_step(&mut self) {
+        self.server_app.update();
+        for client_app in &mut self.client_apps {
+            client_app.update();
+        }
+    }
+
+    pub fn frame_step_n(&mut self, frames: usize) {
+        for _ in 0..frames {
+            self.frame_step();
+        }
+    }
+
+    /// Step until `condition` is true, panics after [`MAX_WAIT_FRAMES`] frames.
+    pub fn wait_until(&mut self, description: &str, mut condition: impl FnMut(&mut Self) -> bool) {
+        for _ in 0..MAX_WAIT_FRAMES {
+            if condition(self) {
+                return;
+            }
+            self.frame_step();
+        }
+        panic!("Timed out waiting for {description}");
+    }
+
+    /// Step until every client is connected and has received its own `Player`.
+    pub fn wait_for_players(&mut self) {
+        let num_clients = self.client_apps.len();
+
+        self.wait_until("clients to connect", |stepper| {
+            (0..num_clients).all(|i| {
+                let client = stepper.clients[i];
+                stepper.client_world(i).get::<Connected>(client).is_some()
+            })
+        });
+
+        self.wait_until("players to be predicted", |stepper| {
+            (0..num_clients).all(|i| stepper.controlled_player(i).is_some())
+        });
+    }
+
+    /// Predicted `Player` controlled by the given client.
+    pub fn controlled_player(&mut self, client: usize) -> Option<Entity> {
+        let world = self.client_world(client);
+        world
+            .query_filtered::<Entity, (With<Player>, With<Predicted>, With<Controlled>)>()
+            .iter(world)
+            .next()
+    }
+}
+
+/// Headless app advancing by exactly one tick per frame.
+fn base_app(tick_duration: Duration) -> App {
+    let mut app = App::new();
+    app.add_plugins((
+        MinimalPlugins,
+        StatesPlugin,
+        InputPlugin,
+        AssetPlugin::default(),
+    ))
+    .init_asset::<Image>()
+    .insert_resource(TimeUpdateStrategy::ManualDuration(tick_duration));
+    app
+}
+
+/// Plugins shared with the game, without rendering, UI or the editor.
+fn add_gameplay_plugins(app: &mut App) {
+    app.add_plugins(
+        PhysicsPlugins::default()
+            .build()
+            // disable Sync as it is handled by lightyear_avian
+            .disable::<avian2d::sync::SyncPlugin>(),
+    )
+    .insert_resource(Gravity(Vec2::ZERO))
+    .add_plugins((SharedPlugin, ProtocolPlugin));
+}
@@ -0,0 +1,111 @@
+use avian2d::prelude::{Collider, Position};
+use bevy::prelude::*;
+use lightyear::prelude::*;
+
+use super::harness::Stepper;
+use crate::{
+    level::{CurrentLevel, Level, WallSpec, level_names},
+    level_editor::LevelEditorPlugin,
+    protocol::{Player, Wall},
+    transform_gizmo::scaled_collider,
+};
+
+#[test]
+fn shipped_levels_load() {
+    let names = level_names();
+    assert!(names.contains(&"arena".to_string()), "{names:?}");
+
+    for name in names {
+        let level = Level::load(&name).unwrap();
+        assert!(!level.walls.is_empty(), "{name} has no walls");
+        assert!(!level.spawn_points.is_empty(), "{name} has no spawn points");
+    }
+}
+
+#[test]
+fn level_names_can_not_leave_the_levels_directory() {
+    assert!(Level::load("../Cargo").is_err());
+    assert!(Level::default().save("../level").is_err());
+}
+
+#[test]
+fn level_roundtrips_through_ron() {
+    let level = Level {
+        walls: vec![WallSpec {
+            position: Vec2::new(10., -20.),
+            size: Vec2::new(40., 80.),
+            rotation: 0.5,
+        }],
+        balls: vec![Vec2::ZERO],
+        spawn_points: vec![Vec2::new(-100., 100.)],
+    };
+
+    let text = ron::to_string(&level).unwrap();
+    assert_eq!(ron::from_str::<Level>(&text).unwrap(), level);
+}
+
+#[test]
+fn players_spawn_at_spawn_points() {
+    let mut stepper = Stepper::new(1);
+    stepper.wait_for_players();
+
+    let spawn_points = Level::load("arena").unwrap().spawn_points;
+    let world = stepper.server_world();
+    let position = world
+        .query_filtered::<&Position, (With<Player>, With<Replicate>)>()
+        .single(world)
+        .unwrap()
+        .0;
+    assert!(spawn_points.contains(&position), "{position}");
+}
+
+#[test]
+fn edited_level_is_sent_to_clients() {
+    let mut stepper = Stepper::new(1);
+    stepper.wait_for_players();
+
+    let mut level = stepper
+        .server_world()
+        .resource::<CurrentLevel>()
+        .level
+        .clone();
+    level.walls.truncate(1);
+    stepper.server_world().resource_mut::<CurrentLevel>().level = level;
+
+    stepper.wait_until("the client to spawn the edited walls", |stepper| {
+        let world = stepper.client_world(0);
+        world.query_filtered::<(), With<Wall>>().iter(world).count() == 1
+    });
+}
+
+#[test]
+fn scaled_wall_is_sent_to_clients() {
+    let mut stepper = Stepper::new(1);
+    stepper.server_app.add_plugins(LevelEditorPlugin);
+    stepper.wait_for_players();
+
+    // what the Scale gizmo does while dragging a wall
+    let world = stepper.server_world();
+    let (wall, transform, collider) = world
+        .query_filtered::<(Entity, &GlobalTransform, &Collider), With<Wall>>()
+        .iter(world)
+        .next()
+        .map(|(wall, transform, collider)| (wall, *transform, collider.clone()))
+        .unwrap();
+    let scaled = scaled_collider(&collider, Vec2::new(2., 1.));
+    let spec = WallSpec::from_wall(&transform, &scaled).unwrap();
+    assert_eq!(
+        spec.size,
+        WallSpec::from_wall(&transform, &collider).unwrap().size * Vec2::new(2., 1.)
+    );
+    world.entity_mut(wall).insert(scaled);
+
+    stepper.wait_until("the client to receive the scaled wall", |stepper| {
+        let world = stepper.client_world(0);
+        world
+            .query_filtered::<(&GlobalTransform, &Collider), With<Wall>>()
+            .iter(world)
+            .filter_map(|(transform, collider)| WallSpec::from_wall(transform, collider))
+            .any(|wall| wall.position == spec.position && wall.size == spec.size)
+    });
+}
@@ -0,0 +1,12 @@
+//! In-process client/server tests.
+
+pub mod harness;
+
+mod admin;
+mod admission;
+mod cvars;
+mod discovery;
+mod edit_history;
+mod level;
+mod movement;
+mod spawning;
@@ -0,0 +1,68 @@
+use avian2d::prelude::Position;
+use bevy::prelude::*;
+use lightyear::prelude::*;
+
+use super::harness::Stepper;
+use crate::protocol::{Player, PlayerId};
+
+/// Position of the server `Player` of the given client.
+fn server_player_position(stepper: &mut Stepper, client_id: u64) -> Vec2 {
+    let world = stepper.server_world();
+    world
+        .query_filtered::<(&PlayerId, &Position), (With<Player>, With<Replicate>)>()
+        .iter(world)
+        .find(|(id, _)| id.0 == PeerId::Netcode(client_id))
+        .map(|(_, position)| position.0)
+        .expect("the server should have spawned a player for the client")
+}
+
+#[test]
+fn every_client_receives_every_player() {
+    let mut stepper = Stepper::new(2);
+    stepper.wait_for_players();
+
+    stepper.wait_until("players to be replicated to all clients", |stepper| {
+        (0..2).all(|client| {
+            let world = stepper.client_world(client);
+            world
+                .query_filtered::<(), (With<Player>, With<Confirmed>)>()
+                .iter(world)
+                .count()
+                == 2
+        })
+    });
+}
+
+#[test]
+fn pressing_right_moves_the_player_on_the_server() {
+    let mut stepper = Stepper::new(1);
+    stepper.wait_for_players();
+
+    let start = server_player_position(&mut stepper, 0);
+
+    // the controlled player reads the keyboard through its `InputMap`
+    stepper
+        .client_world(0)
+        .resource_mut::<ButtonInput<KeyCode>>()
+        .press(KeyCode::KeyD);
+
+    stepper.frame_step_n(30);
+
+    let end = server_player_position(&mut stepper, 0);
+    assert!(
+        end.x > start.x,
+        "player should have moved right on the server: {start} -> {end}"
+    );
+    assert_eq!(end.y, start.y);
+}
+
+#[test]
+fn idle_player_does_not_move() {
+    let mut stepper = Stepper::new(1);
+    stepper.wait_for_players();
+
+    let start = server_player_position(&mut stepper, 0);
+    stepper.frame_step_n(30);
+
+    assert_eq!(server_player_position(&mut stepper, 0), start);
+}
@@ -0,0 +1,42 @@
+use avian2d::prelude::Position;
+use bevy::prelude::*;
+use lightyear::prelude::*;
+
+use super::harness::Stepper;
+use crate::{
+    protocol::Player,
+    server::{MAX_NAME_LEN, deduplicate_name, sanitize_name, spawn_bot},
+};
+
+#[test]
+fn bots_are_interpolated_by_clients() {
+    let mut stepper = Stepper::new(1);
+    stepper.wait_for_players();
+
+    let world = stepper.server_world();
+    let asset_server = world.resource::<AssetServer>().clone();
+    spawn_bot(&mut world.commands(), &asset_server, Vec2::new(30., 40.));
+    world.flush();
+
+    stepper.wait_until("the client to interpolate the bot", |stepper| {
+        let world = stepper.client_world(0);
+        world
+            .query_filtered::<&Position, (With<Player>, With<Interpolated>)>()
+            .iter(world)
+            .any(|position| position.0 == Vec2::new(30., 40.))
+    });
+}
+
+#[test]
+fn deduplicated_names_keep_the_length_limit() {
+    let long = sanitize_name("ABCDEFGHIJKLMNOPQRST");
+    assert_eq!(long.chars().count(), MAX_NAME_LEN);
+
+    let taken = vec![long.clone()];
+    let second = deduplicate_name(long.clone(), &taken);
+    assert_eq!(second, "ABCDEFGHIJKL (2)");
+    assert!(second.chars().count() <= MAX_NAME_LEN);
+
+    let taken = vec![long.clone(), second];
+    assert_eq!(deduplicate_name(long, &taken), "ABCDEFGHIJKL (3)");
+}
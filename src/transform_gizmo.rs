@@ -0,0 +1,477 @@
+//! Gizmos to move, rotate and scale the entity selected in the editor, drawn in the GameView tab.
+//!
+//! Avian bodies are edited through their `Position` and `Rotation`, which the physics syncs to
+//! their `Transform`. On the server, these components are replicated, so clients receive the
+//! edited values like any other movement. Replicated entities edited on a client are
+//! overwritten by the next update from the server.
+//!
+//! Scaling a wall scales its `Collider`, which is sent to clients with the level. Colliders and
+//! scales aren't replicated, so other avian and replicated entities can't be scaled.
+
+use std::any::TypeId;
+
+use avian2d::prelude::{Collider, Position, Rotation};
+use bevy::{prelude::*, reflect::PartialReflect, window::PrimaryWindow};
+use bevy_inspector_egui::bevy_egui::EguiContext;
+use egui_dock::egui;
+use lightyear::prelude::*;
+
+use crate::{
+    edit_history::{Change, Edit},
+    editor::{EditorCamera, UiState, cursor_world_position},
+    level::{CurrentLevel, LevelEntities},
+    level_editor::level_edit,
+    protocol::Wall,
+};
+
+// Sizes in pixels, multiplied by the world size of a pixel to keep them the same at any zoom.
+
+/// Length of the axis handles.
+const HANDLE_LENGTH: f32 = 60.;
+
+/// Half size of the handle in the middle of the gizmo.
+const CENTER_HANDLE_SIZE: f32 = 6.;
+
+const ROTATE_RADIUS: f32 = 50.;
+
+/// Distance from a handle at which it can still be grabbed.
+const GRAB_DISTANCE: f32 = 6.;
+
+const MIN_SCALE: f32 = 0.01;
+
+const X_COLOR: Color = Color::srgb(0.9, 0.2, 0.2);
+const Y_COLOR: Color = Color::srgb(0.2, 0.8, 0.2);
+const FREE_COLOR: Color = Color::srgb(0.3, 0.5, 1.0);
+const ACTIVE_COLOR: Color = Color::srgb(1.0, 0.8, 0.0);
+
+pub struct TransformGizmoPlugin;
+
+impl Plugin for TransformGizmoPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<TransformGizmo>().add_systems(
+            Update,
+            (drag_transform_gizmo, draw_transform_gizmo)
+                .chain()
+                .run_if(resource_exists::<UiState>),
+        );
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum GizmoMode {
+    #[default]
+    Translate,
+    Rotate,
+    Scale,
+}
+
+impl GizmoMode {
+    const ALL: [GizmoMode; 3] = [GizmoMode::Translate, GizmoMode::Rotate, GizmoMode::Scale];
+
+    fn label(self) -> &'static str {
+        match self {
+            GizmoMode::Translate => "Move",
+            GizmoMode::Rotate => "Rotate",
+            GizmoMode::Scale => "Scale",
+        }
+    }
+}
+
+/// Part of the gizmo that is grabbed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum GizmoAxis {
+    X,
+    Y,
+    /// Both axes: the center handle, or the circle of the rotation gizmo.
+    Free,
+}
+
+impl GizmoAxis {
+    /// Keep only the component of `delta` along this axis.
+    fn constrain(self, delta: Vec2) -> Vec2 {
+        match self {
+            GizmoAxis::X => Vec2::new(delta.x, 0.),
+            GizmoAxis::Y => Vec2::new(0., delta.y),
+            GizmoAxis::Free => delta,
+        }
+    }
+
+    fn color(self) -> Color {
+        match self {
+            GizmoAxis::X => X_COLOR,
+            GizmoAxis::Y => Y_COLOR,
+            GizmoAxis::Free => FREE_COLOR,
+        }
+    }
+}
+
+/// Translation, angle and scale of an entity, relative to its parent.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Pose {
+    translation: Vec2,
+    angle: f32,
+    scale: Vec2,
+}
+
+struct Drag {
+    entity: Entity,
+    mode: GizmoMode,
+    axis: GizmoAxis,
+    /// Center of the gizmo in world space when the drag started.
+    origin: Vec2,
+    /// World size of a pixel when the drag started.
+    pixel_size: f32,
+    start_cursor: Vec2,
+    start: Pose,
+    /// Collider of a wall when the drag started, scaled instead of its transform.
+    collider: Option<Collider>,
+    /// Pose given to the entity by the last cursor position.
+    current: Pose,
+    /// Edit restoring the entity as it was before the drag, recorded if the drag changes it.
+    undo: Edit,
+}
+
+impl Drag {
+    fn pose(&self, cursor: Vec2) -> Pose {
+        let delta = cursor - self.start_cursor;
+        let mut pose = self.start;
+        match self.mode {
+            GizmoMode::Translate => pose.translation += self.axis.constrain(delta),
+            GizmoMode::Rotate => {
+                pose.angle += (self.start_cursor - self.origin).angle_to(cursor - self.origin);
+            }
+            GizmoMode::Scale => {
+                let handle_length = HANDLE_LENGTH * self.pixel_size;
+                let factor = match self.axis {
+                    GizmoAxis::Free => Vec2::splat(1. + (delta.x + delta.y) / 2. / handle_length),
+                    axis => Vec2::ONE + axis.constrain(delta) / handle_length,
+                };
+                pose.scale = (pose.scale * factor).max(Vec2::splat(MIN_SCALE));
+            }
+        }
+        pose
+    }
+}
+
+/// Current mode of the gizmo, selected in the toolbar of the GameView tab.
+#[derive(Resource, Default)]
+pub struct TransformGizmo {
+    pub mode: GizmoMode,
+    drag: Option<Drag>,
+}
+
+impl TransformGizmo {
+    pub fn is_dragging(&self) -> bool {
+        self.drag.is_some()
+    }
+}
+
+/// Entities whose scale would not be replicated, or not used by the physics.
+type Unscalable = Or<(
+    With<Replicate>,
+    With<Replicated>,
+    With<Predicted>,
+    With<Interpolated>,
+    With<Confirmed>,
+    (With<Position>, Without<Wall>),
+)>;
+
+/// Buttons to choose the gizmo mode, shown at the top of the GameView tab.
+pub fn transform_gizmo_toolbar(ui: &mut egui::Ui, world: &mut World, selected: Option<Entity>) {
+    let overwritten = selected.is_some_and(|entity| {
+        world.get_entity(entity).is_ok_and(|entity| {
+            entity.contains::<Predicted>()
+                || entity.contains::<Interpolated>()
+                || entity.contains::<Confirmed>()
+        })
+    });
+    let unscalable = selected.is_some_and(|entity| {
+        world
+            .query_filtered::<(), Unscalable>()
+            .get(world, entity)
+            .is_ok()
+    });
+
+    let mut gizmo = world.resource_mut::<TransformGizmo>();
+    ui.horizontal(|ui| {
+        for mode in GizmoMode::ALL {
+            ui.selectable_value(&mut gizmo.mode, mode, mode.label());
+        }
+        if gizmo.mode == GizmoMode::Scale && unscalable {
+            ui.colored_label(
+                egui::Color32::ORANGE,
+                "Only walls and entities without physics can be scaled",
+            );
+        } else if overwritten {
+            ui.colored_label(
+                egui::Color32::ORANGE,
+                "Edits of replicated entities are overwritten by the server",
+            );
+        }
+    });
+}
+
+/// Size of a pixel in world units, as seen by the camera with `projection`.
+fn pixel_size(projection: &Projection) -> f32 {
+    match projection {
+        Projection::Orthographic(orthographic) => orthographic.scale,
+        _ => 1.,
+    }
+}
+
+fn pose(transform: &Transform, position: Option<&Position>, rotation: Option<&Rotation>) -> Pose {
+    Pose {
+        translation: position.map_or(transform.translation.truncate(), |position| position.0),
+        angle: rotation.map_or(transform.rotation.to_euler(EulerRot::XYZ).2, |rotation| {
+            rotation.as_radians()
+        }),
+        scale: transform.scale.truncate(),
+    }
+}
+
+/// Edit which restores the components changed by dragging the gizmo.
+fn edit_before_drag(
+    entity: Entity,
+    transform: &Transform,
+    position: Option<&Position>,
+    rotation: Option<&Rotation>,
+) -> Edit {
+    let change = |type_id, value: Box<dyn PartialReflect>| Change::Component {
+        entity,
+        type_id,
+        value,
+    };
+    let mut changes = vec![change(TypeId::of::<Transform>(), Box::new(*transform))];
+    if let Some(position) = position {
+        changes.push(change(TypeId::of::<Position>(), Box::new(*position)));
+    }
+    if let Some(rotation) = rotation {
+        changes.push(change(TypeId::of::<Rotation>(), Box::new(*rotation)));
+    }
+    Edit(changes)
+}
+
+/// `collider` with its shape scaled by `factor`, keeping the scale of its transform on top.
+pub(crate) fn scaled_collider(collider: &Collider, factor: Vec2) -> Collider {
+    let mut shape = Collider::from(collider.shape().clone());
+    shape.set_scale(factor, 10);
+    let mut scaled = Collider::from(shape.shape_scaled().clone());
+    scaled.set_scale(collider.scale(), 10);
+    scaled
+}
+
+/// Which handle of the gizmo centered on `origin` is under the cursor.
+fn grabbed_axis(mode: GizmoMode, origin: Vec2, cursor: Vec2, pixel_size: f32) -> Option<GizmoAxis> {
+    // in pixels, like the sizes of the gizmo
+    let offset = (cursor - origin) / pixel_size;
+    let on_handle = |along: f32, across: f32| {
+        (0.0..=HANDLE_LENGTH + GRAB_DISTANCE).contains(&along) && across.abs() <= GRAB_DISTANCE
+    };
+
+    match mode {
+        GizmoMode::Rotate => {
+            ((offset.length() - ROTATE_RADIUS).abs() <= GRAB_DISTANCE).then_some(GizmoAxis::Free)
+        }
+        GizmoMode::Translate | GizmoMode::Scale => {
+            if offset.abs().max_element() <= CENTER_HANDLE_SIZE {
+                Some(GizmoAxis::Free)
+            } else if on_handle(offset.x, offset.y) {
+                Some(GizmoAxis::X)
+            } else if on_handle(offset.y, offset.x) {
+                Some(GizmoAxis::Y)
+            } else {
+                None
+            }
+        }
+    }
+}
+
+/// Start dragging a handle of the gizmo, and update the selected entity while it is dragged.
+/// The drag is recorded in the edit history when it is released, if it moved the entity.
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn drag_transform_gizmo(
+    mut ui_state: ResMut<UiState>,
+    mut gizmo: ResMut<TransformGizmo>,
+    mouse: Res<ButtonInput<MouseButton>>,
+    window: Single<&Window, With<PrimaryWindow>>,
+    mut egui_context: Single<&mut EguiContext, With<PrimaryWindow>>,
+    camera: Single<(&Camera, &GlobalTransform, &Projection), With<EditorCamera>>,
+    current_level: Res<CurrentLevel>,
+    mut entity_q: Query<(
+        &GlobalTransform,
+        &mut Transform,
+        Option<&mut Position>,
+        Option<&mut Rotation>,
+    )>,
+    mut walls: ParamSet<(LevelEntities, Query<&mut Collider, With<Wall>>)>,
+    unscalable_q: Query<(), Unscalable>,
+) {
+    if !mouse.pressed(MouseButton::Left) {
+        let moved = gizmo.drag.take().filter(|drag| drag.current != drag.start);
+        if let Some(drag) = moved {
+            ui_state.history_mut().record(drag.undo);
+        }
+        return;
+    }
+
+    let (camera, camera_transform, projection) = *camera;
+    let Some(cursor) = cursor_world_position(&window, camera, camera_transform) else {
+        return;
+    };
+
+    if mouse.just_pressed(MouseButton::Left) {
+        gizmo.drag = None;
+        // clicks on the toolbar are for egui
+        if egui_context.get_mut().wants_pointer_input() {
+            return;
+        }
+        let Some(entity) = ui_state.selected_entity() else {
+            return;
+        };
+        let Ok((global_transform, transform, position, rotation)) = entity_q.get(entity) else {
+            return;
+        };
+        let origin = global_transform.translation().truncate();
+        let mode = gizmo.mode;
+        if mode == GizmoMode::Scale && unscalable_q.contains(entity) {
+            return;
+        }
+        let pixel_size = pixel_size(projection);
+        if let Some(axis) = grabbed_axis(mode, origin, cursor, pixel_size) {
+            let mut start = pose(transform, position, rotation);
+            let mut undo = edit_before_drag(entity, transform, position, rotation);
+            let collider = walls
+                .p1()
+                .get(entity)
+                .ok()
+                .filter(|_| mode == GizmoMode::Scale)
+                .cloned();
+            if collider.is_some() {
+                // the collider is scaled relative to its shape when the drag started, and
+                // restored with the level since colliders can't be reflected
+                start.scale = Vec2::ONE;
+                undo = level_edit(&current_level, walls.p0().level());
+            }
+            gizmo.drag = Some(Drag {
+                entity,
+                mode,
+                axis,
+                origin,
+                pixel_size,
+                start_cursor: cursor,
+                start,
+                collider,
+                current: start,
+                undo,
+            });
+        }
+        return;
+    }
+
+    let Some(drag) = &mut gizmo.drag else {
+        return;
+    };
+    let Ok((_, mut transform, position, rotation)) = entity_q.get_mut(drag.entity) else {
+        return;
+    };
+
+    let pose = drag.pose(cursor);
+    drag.current = pose;
+    // only write the edited values, so that unchanged components are not replicated again
+    match drag.mode {
+        GizmoMode::Translate => match position {
+            Some(mut position) => {
+                position.set_if_neq(Position(pose.translation));
+            }
+            None => {
+                let translation = pose.translation.extend(transform.translation.z);
+                transform.set_if_neq(transform.with_translation(translation));
+            }
+        },
+        GizmoMode::Rotate => match rotation {
+            Some(mut rotation) => {
+                rotation.set_if_neq(Rotation::radians(pose.angle));
+            }
+            None => {
+                let rotation = Quat::from_rotation_z(pose.angle);
+                transform.set_if_neq(transform.with_rotation(rotation));
+            }
+        },
+        GizmoMode::Scale => match (&drag.collider, walls.p1().get_mut(drag.entity)) {
+            (Some(start_collider), Ok(mut collider)) => {
+                *collider = scaled_collider(start_collider, pose.scale);
+            }
+            _ => {
+                let scale = pose.scale.extend(transform.scale.z);
+                transform.set_if_neq(transform.with_scale(scale));
+            }
+        },
+    }
+}
+
+fn draw_transform_gizmo(
+    ui_state: Res<UiState>,
+    gizmo: Res<TransformGizmo>,
+    projection: Single<&Projection, With<EditorCamera>>,
+    entity_q: Query<&GlobalTransform>,
+    mut gizmos: Gizmos,
+) {
+    let Some(transform) = ui_state
+        .selected_entity()
+        .and_then(|entity| entity_q.get(entity).ok())
+    else {
+        return;
+    };
+    let origin = transform.translation().truncate();
+    let pixel_size = pixel_size(&projection);
+    let handle_length = HANDLE_LENGTH * pixel_size;
+    let center_handle_size = CENTER_HANDLE_SIZE * pixel_size;
+    let rotate_radius = ROTATE_RADIUS * pixel_size;
+
+    let color = |axis: GizmoAxis| match &gizmo.drag {
+        Some(drag) if drag.axis == axis => ACTIVE_COLOR,
+        _ => axis.color(),
+    };
+    let center = Isometry2d::from_translation(origin);
+
+    match gizmo.mode {
+        GizmoMode::Translate => {
+            gizmos.arrow_2d(
+                origin,
+                origin + Vec2::X * handle_length,
+                color(GizmoAxis::X),
+            );
+            gizmos.arrow_2d(
+                origin,
+                origin + Vec2::Y * handle_length,
+                color(GizmoAxis::Y),
+            );
+            gizmos.rect_2d(
+                center,
+                Vec2::splat(center_handle_size * 2.),
+                color(GizmoAxis::Free),
+            );
+        }
+        GizmoMode::Rotate => {
+            let angle = transform.rotation().to_euler(EulerRot::XYZ).2;
+            gizmos.circle_2d(center, rotate_radius, color(GizmoAxis::Free));
+            gizmos.line_2d(
+                origin,
+                origin + Vec2::from_angle(angle) * rotate_radius,
+                color(GizmoAxis::Free),
+            );
+        }
+        GizmoMode::Scale => {
+            for axis in [GizmoAxis::X, GizmoAxis::Y] {
+                let end = origin + axis.constrain(Vec2::ONE) * handle_length;
+                gizmos.line_2d(origin, end, color(axis));
+                gizmos.rect_2d(
+                    Isometry2d::from_translation(end),
+                    Vec2::splat(center_handle_size),
+                    color(axis),
+                );
+            }
+            gizmos.circle_2d(center, center_handle_size, color(GizmoAxis::Free));
+        }
+    }
+}
@@ -0,0 +1,91 @@
+//! Transport used between clients and the server.
+//!
+//! Native games are played over UDP. Browsers can't use raw UDP, so the server can also
+//! listen on WebTransport and WebSocket for WASM clients, each served by its own server
+//! entity alongside the UDP one. Server sockets and certificates only exist natively.
+//! The in-memory transport connects a server and clients running in the same process
+//! through crossbeam channels, without any socket. It can't link separate processes, so it
+//! is used by the `host` mode, tests and benchmarks.
+
+use core::net::{IpAddr, Ipv4Addr, SocketAddr};
+
+use bevy::prelude::*;
+use clap::ValueEnum;
+use lightyear::crossbeam::CrossbeamIo;
+#[cfg(not(target_family = "wasm"))]
+use lightyear::{
+    prelude::server::{Identity, WebSocketServerIo},
+    websocket::server::ServerConfig as WebSocketServerConfig,
+};
+use serde::{Deserialize, Serialize};
+
+/// Address of the WebSocket listener of the server.
+///
+/// UDP listens on `SERVER_ADDR`. WebSocket runs over TCP so it could share the port, but a
+/// separate one avoids confusion when both are opened in a firewall.
+pub const WEBSOCKET_SERVER_ADDR: SocketAddr =
+    SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), 5001);
+
+/// Address of the WebTransport listener of the server, which runs over UDP (QUIC) and so
+/// can't share the port of the UDP listener.
+pub const WEBTRANSPORT_SERVER_ADDR: SocketAddr =
+    SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), 5002);
+
+#[derive(
+    Resource, ValueEnum, Serialize, Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq,
+)]
+pub enum Transport {
+    #[default]
+    Udp,
+    Memory,
+    #[value(name = "webtransport")]
+    WebTransport,
+    #[value(name = "websocket")]
+    WebSocket,
+}
+
+/// Transports the server listens on in addition to UDP.
+#[derive(Resource, Default)]
+pub struct ServerTransportOptions {
+    /// Also accept WebTransport clients on [`WEBTRANSPORT_SERVER_ADDR`].
+    pub webtransport: bool,
+    /// Also accept WebSocket clients on [`WEBSOCKET_SERVER_ADDR`].
+    pub websocket: bool,
+}
+
+/// Generate a self-signed certificate for WebTransport.
+///
+/// Browsers only accept it when given its digest, so the digest is logged for clients
+/// (passed with `--certificate-digest` or the `certificate-digest` URL parameter).
+#[cfg(not(target_family = "wasm"))]
+pub fn self_signed_identity() -> Result<Identity> {
+    let identity = Identity::self_signed(["localhost", "127.0.0.1", "::1"])?;
+    let digest = identity.certificate_chain().as_slice()[0].hash();
+    info!("WebTransport certificate digest: {digest}");
+    Ok(identity)
+}
+
+/// Plain (unencrypted) WebSocket listener, for local development.
+#[cfg(not(target_family = "wasm"))]
+pub fn websocket_server_io() -> WebSocketServerIo {
+    WebSocketServerIo {
+        config: WebSocketServerConfig::builder()
+            .with_bind_address(WEBSOCKET_SERVER_ADDR)
+            .with_no_encryption(),
+    }
+}
+
+/// Server ends of in-memory links that are waiting to be attached to the server,
+/// with the address identifying each client.
+#[derive(Resource, Default)]
+pub struct MemoryServerLinks(pub Vec<(SocketAddr, CrossbeamIo)>);
+
+/// Client end of an in-memory link, taken by the client when it connects.
+#[derive(Resource)]
+pub struct MemoryClientLink(pub Option<CrossbeamIo>);
+
+/// Create an in-memory link between the client identified by `client_addr` and the server.
+pub fn memory_link(client_addr: SocketAddr) -> ((SocketAddr, CrossbeamIo), MemoryClientLink) {
+    let (client_io, server_io) = CrossbeamIo::new_pair();
+    ((client_addr, server_io), MemoryClientLink(Some(client_io)))
+}